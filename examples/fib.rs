@@ -0,0 +1,30 @@
+use std::{fs::read_to_string, time::{Duration, Instant}};
+
+use neptune::{session::Session, vm::Vm, interpreter::Object, error::Error};
+
+// Times fib.nt (or whatever script is passed in) on the tree-walker and on the vm, keeping the
+// best of a few runs of each. Run it with `cargo run --release --example fib [path] [runs]`.
+// examples/locals.nt spends more of its time on local variables than fib.nt does.
+
+fn best(runs: usize, mut run: impl FnMut() -> Result<Object, Error>) -> Duration {
+    (0..runs).map(|_| {
+        let start = Instant::now();
+        if run().is_err() {
+            panic!("the script failed");
+        }
+        start.elapsed()
+    }).min().unwrap()
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().unwrap_or_else(|| concat!(env!("CARGO_MANIFEST_DIR"), "/fib.nt").to_string());
+    let runs = args.next().and_then(|n| n.parse().ok()).unwrap_or(5);
+    let source = read_to_string(&path).expect("Error reading the file");
+
+    let walked = best(runs, || Session::new().run(&source));
+    let compiled = best(runs, || Vm::new().run(&source));
+    println!("{} (best of {})", path, runs);
+    println!("  tree-walker  {:?}", walked);
+    println!("  vm           {:?}", compiled);
+}
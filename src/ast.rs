@@ -1,71 +1,287 @@
 use core::fmt;
+use std::rc::Rc;
 
-use crate::token::Token;
+use crate::token::{Token, Span};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Node {
     BinaryOperator { //
         id: usize,
+        span: Span,
         left: Box<Node>,
         operator: Token,
         right: Box<Node>
     },
     Literal { //
         id: usize,
+        span: Span,
         value: Literal
     },
     UnaryOperator { //
         id: usize,
+        span: Span,
         operator: Token,
         child: Box<Node>
     },
     Logical { //
         id: usize,
+        span: Span,
         left: Box<Node>,
         operator: Token,
         right: Box<Node>
     },
     Declare { //
         id: usize,
+        span: Span,
         name: Token,
         value: Box<Node>
     },
     Assign { //
         id: usize,
+        span: Span,
         name: Token,
         value: Box<Node>
     },
     If { //
         id: usize,
+        span: Span,
         condition: Box<Node>,
         body: Box<Node>,
         else_block: Option<Box<Node>>
     },
     While {
         id: usize,
+        span: Span,
         condition: Box<Node>,
+        body: Box<Node>,
+        increment: Option<Box<Node>> // run after every iteration, even ones cut short by `continue`
+    },
+    ForIn { // for variable in iterable body
+        id: usize,
+        span: Span,
+        variable: Token,
+        iterable: Box<Node>,
         body: Box<Node>
     },
+    Range {
+        id: usize,
+        span: Span,
+        start: Box<Node>,
+        end: Box<Node>,
+        step: Option<Box<Node>>,
+        inclusive: bool,
+        operator: Token
+    },
+    Break {
+        id: usize,
+        span: Span,
+        keyword: Token
+    },
+    Continue {
+        id: usize,
+        span: Span,
+        keyword: Token
+    },
     Variable {
         id: usize,
+        span: Span,
         name: Token
     },
-    DeclareFn {
+    DeclareFn { // the parameters and body are shared with every function made from this
         id: usize,
+        span: Span,
         name: Token,
-        args: Vec<Token>,
-        body: Box<Node>
+        args: Rc<Vec<Param>>,
+        body: Rc<Node>
+    },
+    Lambda { // fn (args) { body } or |args| expression
+        id: usize,
+        span: Span,
+        keyword: Token,
+        args: Rc<Vec<Param>>,
+        body: Rc<Node>
     },
     FnCall {
         id: usize,
+        span: Span,
         name: Box<Node>,
-        args: Vec<Node>
+        args: Vec<Node>,
+        kwargs: Vec<(Token, Node)>, // name: value
+        paren: Token // the `(`, which is where the call is reported to happen
     },
     Return {
         id: usize,
+        span: Span,
+        keyword: Token,
         value: Box<Node>
     },
-    Block(Vec<Node>),
+    Throw {
+        id: usize,
+        span: Span,
+        keyword: Token,
+        value: Box<Node>
+    },
+    Try { // try { body } catch (name) { handler } finally { cleanup }, needing a catch or finally
+        id: usize,
+        span: Span,
+        body: Box<Node>,
+        catch: Option<(Token, Box<Node>)>,
+        finally: Option<Box<Node>>
+    },
+    Class {
+        id: usize,
+        span: Span,
+        name: Token,
+        superclass: Option<Box<Node>>, // a Node::Variable
+        methods: Vec<Node> // all Node::DeclareFn
+    },
+    Get { // object.name
+        id: usize,
+        span: Span,
+        object: Box<Node>,
+        name: Token
+    },
+    Set { // object.name = value
+        id: usize,
+        span: Span,
+        object: Box<Node>,
+        name: Token,
+        value: Box<Node>
+    },
+    This {
+        id: usize,
+        span: Span,
+        keyword: Token
+    },
+    Super { // super.method
+        id: usize,
+        span: Span,
+        keyword: Token,
+        method: Token
+    },
+    Interpolation { // alternating literal strings and embedded expressions
+        id: usize,
+        span: Span,
+        parts: Vec<Node>
+    },
+    List {
+        id: usize,
+        span: Span,
+        items: Vec<Node>
+    },
+    Map {
+        id: usize,
+        span: Span,
+        entries: Vec<(Node, Node)>,
+        brace: Token
+    },
+    Index { // object[index]
+        id: usize,
+        span: Span,
+        object: Box<Node>,
+        index: Box<Node>,
+        bracket: Token
+    },
+    Slice { // object[start:end], either bound can be left out
+        id: usize,
+        span: Span,
+        object: Box<Node>,
+        start: Option<Box<Node>>,
+        end: Option<Box<Node>>,
+        bracket: Token
+    },
+    SetIndex { // object[index] = value
+        id: usize,
+        span: Span,
+        object: Box<Node>,
+        index: Box<Node>,
+        value: Box<Node>,
+        bracket: Token
+    },
+    Block {
+        span: Span,
+        body: Vec<Node>
+    },
+}
+
+// A function parameter: `name`, `name = default` or `...name`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Param {
+    pub name: Token,
+    pub default: Option<Node>,
+    pub variadic: bool // collects any extra positional arguments into a list
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.default {
+            _ if self.variadic => write!(f, "...{}", self.name.value),
+            Some(default) => write!(f, "{} = {}", self.name.value, default),
+            None => write!(f, "{}", self.name.value)
+        }
+    }
+}
+
+impl Node {
+    // where the node came from in the source, for diagnostics and tooling
+    pub fn span(&self) -> Span {
+        match self {
+            Node::BinaryOperator { span, .. } |
+            Node::Literal { span, .. } |
+            Node::UnaryOperator { span, .. } |
+            Node::Logical { span, .. } |
+            Node::Declare { span, .. } |
+            Node::Assign { span, .. } |
+            Node::If { span, .. } |
+            Node::While { span, .. } |
+            Node::ForIn { span, .. } |
+            Node::Range { span, .. } |
+            Node::Break { span, .. } |
+            Node::Continue { span, .. } |
+            Node::Variable { span, .. } |
+            Node::DeclareFn { span, .. } |
+            Node::Lambda { span, .. } |
+            Node::FnCall { span, .. } |
+            Node::Return { span, .. } |
+            Node::Throw { span, .. } |
+            Node::Try { span, .. } |
+            Node::Class { span, .. } |
+            Node::Get { span, .. } |
+            Node::Set { span, .. } |
+            Node::This { span, .. } |
+            Node::Super { span, .. } |
+            Node::Interpolation { span, .. } |
+            Node::List { span, .. } |
+            Node::Map { span, .. } |
+            Node::Index { span, .. } |
+            Node::Slice { span, .. } |
+            Node::SetIndex { span, .. } |
+            Node::Block { span, .. } => *span
+        }
+    }
+
+    // whether the node produces a value (as opposed to a declaration or control flow)
+    pub fn is_expression(&self) -> bool {
+        matches!(
+            self,
+            Node::BinaryOperator { .. } |
+            Node::Literal { .. } |
+            Node::UnaryOperator { .. } |
+            Node::Logical { .. } |
+            Node::Variable { .. } |
+            Node::FnCall { .. } |
+            Node::Lambda { .. } |
+            Node::Interpolation { .. } |
+            Node::List { .. } |
+            Node::Map { .. } |
+            Node::Range { .. } |
+            Node::Index { .. } |
+            Node::Slice { .. } |
+            Node::Get { .. } |
+            Node::This { .. } |
+            Node::Super { .. }
+        )
+    }
 }
 
 impl fmt::Display for Node {
@@ -79,11 +295,63 @@ impl fmt::Display for Node {
             Node::Assign { name, value, .. } => write!(f, "{:?} = {}", name, value),
             Node::If { condition, .. } => write!(f, "if ({})", condition),
             Node::While { condition, .. } => write!(f, "while ({})", condition),
+            Node::ForIn { variable, iterable, .. } => write!(f, "for {} in {}", variable.value, iterable),
+            Node::Range { start, end, step, operator, .. } => {
+                write!(f, "{}{}{}", start, operator.value, end)?;
+                match step {
+                    Some(step) => write!(f, " step {}", step),
+                    None => Ok(())
+                }
+            },
+            Node::Break { .. } => write!(f, "break"),
+            Node::Continue { .. } => write!(f, "continue"),
             Node::Variable { name, .. } => write!(f, "{}", name.value),
             Node::DeclareFn { name, .. } => write!(f, "fn {}", name.value),
-            Node::FnCall { name, args, ..} => write!(f, "{}({:?})", name, args),
+            Node::Lambda { args, .. } => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "fn ({})", args.join(", "))
+            },
+            Node::FnCall { name, args, kwargs, .. } => {
+                let mut all: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                all.extend(kwargs.iter().map(|(k, v)| format!("{}: {}", k.value, v)));
+                write!(f, "{}({})", name, all.join(", "))
+            },
             Node::Return { value, .. } => write!(f, "return {}", value),
-            Node::Block(v) => write!(f, "{:?}", v)
+            Node::Throw { value, .. } => write!(f, "throw {}", value),
+            Node::Try { catch: Some((name, _)), .. } => write!(f, "try catch ({})", name.value),
+            Node::Try { .. } => write!(f, "try"),
+            Node::Class { name, superclass: Some(superclass), .. } => write!(f, "class {} < {}", name.value, superclass),
+            Node::Class { name, .. } => write!(f, "class {}", name.value),
+            Node::Get { object, name, .. } => write!(f, "{}.{}", object, name.value),
+            Node::Set { object, name, value, .. } => write!(f, "{}.{} = {}", object, name.value, value),
+            Node::This { .. } => write!(f, "this"),
+            Node::Super { method, .. } => write!(f, "super.{}", method.value),
+            Node::Interpolation { parts, .. } => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        Node::Literal { value, .. } => write!(f, "{}", value)?,
+                        expr => write!(f, "{{{}}}", expr)?
+                    }
+                }
+                write!(f, "\"")
+            },
+            Node::List { items, .. } => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            },
+            Node::Map { entries, .. } => {
+                let entries: Vec<String> = entries.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            },
+            Node::Index { object, index, .. } => write!(f, "{}[{}]", object, index),
+            Node::Slice { object, start, end, .. } => {
+                let start = start.as_ref().map_or(String::new(), |s| s.to_string());
+                let end = end.as_ref().map_or(String::new(), |e| e.to_string());
+                write!(f, "{}[{}:{}]", object, start, end)
+            },
+            Node::SetIndex { object, index, value, .. } => write!(f, "{}[{}] = {}", object, index, value),
+            Node::Block { body, .. } => write!(f, "{:?}", body)
         }   
     }
 }
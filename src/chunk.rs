@@ -0,0 +1,138 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::{
+    token::Token,
+    ast::Param,
+    interpreter::{Object, repr}
+};
+
+// One instruction for the vm. Operands index into the chunk (constants, functions) or the
+// stack (slots), and jumps are absolute positions in `code`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    None,
+    True,
+    False,
+    Pop,
+
+    GetLocal(u16), // slots count from the function being called, which is slot 0
+    SetLocal(u16), // assignments pop the value they store
+    GetUpvalue(u16),
+    SetUpvalue(u16),
+    GetGlobal(u32),
+    SetGlobal(u32),
+    DefineGlobal(u32),
+    GetProperty, // the name is the instruction's token
+    SetProperty,
+    GetSuper, // [this, superclass] -> the bound method
+
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+    Equal,
+    NotEqual,
+    In,
+    Negate,
+    Not,
+    And, // both operands are already evaluated; these pick one
+    Or,
+
+    Jump(u32),
+    JumpIfFalse(u32), // pops the condition
+    JumpIfBound(u16, u32), // skips a parameter's default when the caller gave it a value
+
+    Call(u8),
+    CallKw(u8, u16), // positional arguments, then the values of the keyword names in `keywords`
+    TailCall(u8), // a call that's returned straight away, made in place of the current frame
+    TailCallKw(u8, u16),
+    Closure(u32),
+    CloseUpvalue, // like `Pop`, but for a local something has captured
+    Return,
+
+    Class(u16, bool), // pops that many methods, and peeks at the superclass below them if there is one
+    Inherit, // checks that the value about to be inherited from is a class
+
+    List(u32),
+    Map(u32), // that many key and value pairs
+    Index,
+    Slice(bool, bool), // whether there's a start and an end
+    SetIndex,
+    Range(bool, bool), // whether it's inclusive, and whether there's a step
+    Interpolate(u32),
+
+    Throw,
+    Try(u32), // where the handler starts
+    EndTry,
+    Rethrow, // raises the error the innermost handler caught, once `finally` has run
+
+    IterStart,
+    IterNext(u32), // pushes the next item, or jumps once there are none
+    IterEnd
+}
+
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub sites: Vec<u32>, // for each instruction, the token in `tokens` that errors point at
+    pub tokens: Vec<Token>,
+    pub constants: Vec<Object>,
+    pub functions: Vec<Rc<Proto>>,
+    pub keywords: Vec<Vec<Token>> // the names given to keyword arguments, per call
+}
+
+impl Chunk {
+    pub fn token(&self, ip: usize) -> &Token {
+        &self.tokens[self.sites[ip] as usize]
+    }
+}
+
+// How a closure gets hold of a variable from the function around it: either one of that
+// function's locals, or one of its own upvalues.
+#[derive(Debug, Clone, Copy)]
+pub struct Capture {
+    pub local: bool,
+    pub index: u16
+}
+
+// A compiled function, before it's closed over anything.
+pub struct Proto {
+    pub name: Token,
+    pub params: Vec<Param>,
+    pub simple: bool, // no defaults or variadics, so arguments can go straight into their slots
+    pub is_initializer: bool,
+    pub chunk: Chunk,
+    pub captures: Vec<Capture>
+}
+
+// a disassembly, with every function the chunk defines after it
+impl Display for Proto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "== {} ==", self.name.value)?;
+        let chunk = &self.chunk;
+        for (ip, op) in chunk.code.iter().enumerate() {
+            let token = chunk.token(ip);
+            write!(f, "{:04} [{}:{}] {:?}", ip, token.line, token.column, op)?;
+            match op {
+                Op::Constant(i) => write!(f, " ({})", repr(&chunk.constants[*i as usize]))?,
+                Op::GetProperty | Op::SetProperty | Op::GetSuper => write!(f, " ({})", token.value)?,
+                Op::Closure(i) => write!(f, " ({})", chunk.functions[*i as usize].name.value)?,
+                Op::CallKw(_, i) | Op::TailCallKw(_, i) => {
+                    let names: Vec<&str> = chunk.keywords[*i as usize].iter().map(|k| k.value.as_str()).collect();
+                    write!(f, " ({})", names.join(", "))?
+                },
+                _ => {}
+            }
+            writeln!(f)?;
+        }
+        for function in &chunk.functions {
+            write!(f, "\n{}", function)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use std::{collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{
+    token::{Token, TokenType},
+    function::Function,
+    interpreter::Object
+};
+
+pub struct Class {
+    pub name: Token,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<String, Function>
+}
+
+impl Class {
+    // a class the language provides, like `Error`, with no methods until some are added
+    pub fn builtin(name: &str) -> Self {
+        let name = Token::new(TokenType::Name(name.to_string()), name.to_string(), 0, 0);
+        Self { name, superclass: None, methods: HashMap::new() }
+    }
+
+    // looks up the superclass chain when the class doesn't define the method itself
+    pub fn find_method(&self, name: &str) -> Option<&Function> {
+        match self.methods.get(name) {
+            Some(method) => Some(method),
+            None => self.superclass.as_ref().and_then(|s| s.find_method(name))
+        }
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: HashMap<String, Object>
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Self { class, fields: HashMap::new() }
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name.line {
+            0 => write!(f, "<class '{}'>", self.name.value), // built in, like `Error`
+            _ => write!(f, "<class '{}' at [{}:{}]>", self.name.value, self.name.line, self.name.column)
+        }
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = &self.class.name;
+        match name.line {
+            0 => write!(f, "<instance of '{}'>", name.value),
+            _ => write!(f, "<instance of '{}' at [{}:{}]>", name.value, name.line, name.column)
+        }
+    }
+}
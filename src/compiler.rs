@@ -0,0 +1,757 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{Node, Literal, Param},
+    token::{Token, TokenType},
+    chunk::{Op, Chunk, Proto, Capture},
+    interpreter::Object,
+    vm::Globals,
+    error::Error,
+    diagnostic::Diagnostic
+};
+
+struct Local {
+    name: String,
+    depth: usize,
+    captured: bool // closed over by a closure, so it has to outlive its slot
+}
+
+struct Loop {
+    depth: usize, // the scope depth outside the loop
+    tries: usize, // how many `try` blocks were open outside it
+    breaks: Vec<usize>,
+    continues: Vec<usize>
+}
+
+// A `try` the code being compiled is inside of. Anything that jumps out of it (a `return`,
+// `break` or `continue`) takes its handler down and runs its `finally` on the way.
+#[derive(Clone)]
+struct TryBlock {
+    finally: Option<Node>,
+    handler: bool // false inside a `catch` with no `finally`, where nothing is left to undo
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    Script,
+    Function,
+    Method,
+    Initializer
+}
+
+// the function currently being compiled, of those nested inside each other
+struct State {
+    name: Token,
+    params: Vec<Param>,
+    kind: Kind,
+    chunk: Chunk,
+    site: u32, // the token instructions are being blamed on
+    locals: Vec<Local>,
+    depth: usize,
+    captures: Vec<Capture>,
+    loops: Vec<Loop>,
+    tries: Vec<TryBlock>,
+    trying: usize // how many `try` statements deep, counting their `finally` blocks as well
+}
+
+impl State {
+    fn new(name: Token, params: Vec<Param>, kind: Kind) -> Self {
+        // slot 0 holds the function being called, or `this` in a method
+        let this = if matches!(kind, Kind::Method | Kind::Initializer) { "this" } else { "" };
+        let chunk = Chunk { tokens: vec![name.clone()], ..Chunk::default() };
+        let locals = vec![Local { name: this.to_string(), depth: 0, captured: false }];
+        Self { name, params, kind, chunk, site: 0, locals, depth: 0, captures: vec![], loops: vec![], tries: vec![], trying: 0 }
+    }
+}
+
+// Compiles a resolved program into bytecode for the vm.
+//
+// Locals live in stack slots, numbered at compile time, and the closures that outlive them
+// capture them as upvalues. Anything that isn't a local is a global, found by slot too.
+// Scoping follows the resolver exactly, so both engines see the same variables.
+pub struct Compiler<'a> {
+    globals: &'a mut Globals,
+    states: Vec<State>
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(globals: &'a mut Globals) -> Self {
+        Self { globals, states: vec![] }
+    }
+
+    // the function the vm starts in. like `Session::run`, a trailing expression is the program's value.
+    pub fn compile(mut self, nodes: &[Node]) -> Result<Rc<Proto>, Error> {
+        let name = Token::new(TokenType::Name("<script>".to_string()), "<script>".to_string(), 0, 0);
+        self.states.push(State::new(name, vec![], Kind::Script));
+
+        match nodes.split_last() {
+            Some((last, rest)) if last.is_expression() => {
+                for node in rest {
+                    self.statement(node)?;
+                }
+                self.expression(last)?;
+            },
+            _ => {
+                for node in nodes {
+                    self.statement(node)?;
+                }
+                self.emit(Op::None);
+            }
+        }
+        self.emit(Op::Return);
+        Ok(Rc::new(self.finish()))
+    }
+
+    fn state(&mut self) -> &mut State {
+        self.states.last_mut().unwrap()
+    }
+
+    fn finish(&mut self) -> Proto {
+        let state = self.states.pop().unwrap();
+        Proto {
+            simple: state.params.iter().all(|p| p.default.is_none() && !p.variadic),
+            name: state.name,
+            params: state.params,
+            is_initializer: state.kind == Kind::Initializer,
+            chunk: state.chunk,
+            captures: state.captures
+        }
+    }
+
+    // blames the instructions that follow on `token`
+    fn mark(&mut self, token: &Token) {
+        let chunk = &mut self.state().chunk;
+        if chunk.tokens.last() != Some(token) {
+            chunk.tokens.push(token.clone());
+        }
+        let site = chunk.tokens.len() as u32 - 1;
+        self.state().site = site;
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let state = self.state();
+        state.chunk.code.push(op);
+        state.chunk.sites.push(state.site);
+        state.chunk.code.len() - 1
+    }
+
+    // points the jump at `at` to `target`
+    fn patch_to(&mut self, at: usize, target: usize) {
+        let target = target as u32;
+        match &mut self.state().chunk.code[at] {
+            Op::Jump(t) | Op::JumpIfFalse(t) | Op::JumpIfBound(_, t) | Op::Try(t) | Op::IterNext(t) => *t = target,
+            _ => {}
+        }
+    }
+
+    // points the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let here = self.state().chunk.code.len();
+        self.patch_to(at, here);
+    }
+
+    fn constant(&mut self, value: Object) -> Result<u32, Error> {
+        let constants = &mut self.state().chunk.constants;
+        if let Object::String(s) = &value {
+            if let Some(i) = constants.iter().position(|c| matches!(c, Object::String(c) if c == s)) {
+                return Ok(i as u32)
+            }
+        }
+        constants.push(value);
+        let len = constants.len();
+        self.limit(len, u32::MAX as usize, "constants")?;
+        Ok(len as u32 - 1)
+    }
+
+    fn limit(&mut self, count: usize, max: usize, what: &str) -> Result<(), Error> {
+        if count > max {
+            let state = self.state();
+            let token = &state.chunk.tokens[state.site as usize];
+            return Err(Error::Syntax(Diagnostic::at("E0400", format!("Too many {} in one function for the vm (the most is {}).", what, max), token)))
+        }
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.state();
+        state.depth -= 1;
+        while state.locals.last().is_some_and(|l| l.depth > state.depth) {
+            let local = state.locals.pop().unwrap();
+            let op = if local.captured { Op::CloseUpvalue } else { Op::Pop };
+            state.chunk.code.push(op);
+            state.chunk.sites.push(state.site);
+        }
+    }
+
+    // the value on top of the stack becomes the local `name`
+    fn add_local(&mut self, name: &str) -> Result<u16, Error> {
+        let state = self.state();
+        let depth = state.depth;
+        state.locals.push(Local { name: name.to_string(), depth, captured: false });
+        let len = state.locals.len();
+        self.limit(len, u16::MAX as usize, "local variables")?;
+        Ok(len as u16 - 1)
+    }
+
+    // where a declaration of `name` goes: a global, a local it's redeclaring in the same
+    // scope (the tree-walker just overwrites those), or a new local on top of the stack
+    fn target(&mut self, name: &Token) -> Result<Target, Error> {
+        let state = self.state();
+        if state.depth == 0 {
+            return Ok(Target::Global(self.globals.slot(&name.value)))
+        }
+        let depth = state.depth;
+        match state.locals.iter().rposition(|l| l.name == name.value && l.depth == depth) {
+            Some(slot) => Ok(Target::Existing(slot as u16)),
+            None => Ok(Target::New(self.add_local(&name.value)?))
+        }
+    }
+
+    // a declaration that's the whole body of an `if` or `while` takes its slot even when
+    // the body is skipped, so the slot is held with `none` first, like the tree-walker's store()
+    fn reserve(&mut self, body: &Node) -> Result<(), Error> {
+        match body {
+            Node::Declare { name, .. } | Node::DeclareFn { name, .. } | Node::Class { name, .. } => {
+                if let Target::New(_) = self.target(name)? {
+                    self.emit(Op::None);
+                }
+            },
+            Node::If { body, else_block, .. } => {
+                self.reserve(body)?;
+                if let Some(else_block) = else_block {
+                    self.reserve(else_block)?;
+                }
+            },
+            Node::While { body, .. } => self.reserve(body)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // stores the value on top of the stack in a declaration's target. new locals are
+    // already where the value is.
+    fn store(&mut self, target: Target, name: &Token) {
+        self.mark(name);
+        match target {
+            Target::Global(slot) => { self.emit(Op::DefineGlobal(slot)); },
+            Target::Existing(slot) => { self.emit(Op::SetLocal(slot)); },
+            Target::New(_) => {}
+        }
+    }
+
+    fn resolve_local(&self, level: usize, name: &str) -> Option<u16> {
+        self.states[level].locals.iter().rposition(|l| l.name == name).map(|i| i as u16)
+    }
+
+    // finds `name` in the functions around the one at `level`, capturing it on the way in
+    fn resolve_upvalue(&mut self, level: usize, name: &str) -> Result<Option<u16>, Error> {
+        if level == 0 {
+            return Ok(None)
+        }
+        let capture = if let Some(slot) = self.resolve_local(level - 1, name) {
+            self.states[level - 1].locals[slot as usize].captured = true;
+            Capture { local: true, index: slot }
+        } else if let Some(index) = self.resolve_upvalue(level - 1, name)? {
+            Capture { local: false, index }
+        } else {
+            return Ok(None)
+        };
+
+        let captures = &mut self.states[level].captures;
+        if let Some(i) = captures.iter().position(|c| c.local == capture.local && c.index == capture.index) {
+            return Ok(Some(i as u16))
+        }
+        captures.push(capture);
+        let len = captures.len();
+        self.limit(len, u16::MAX as usize, "captured variables")?;
+        Ok(Some(len as u16 - 1))
+    }
+
+    fn get_variable(&mut self, name: &Token) -> Result<(), Error> {
+        self.mark(name);
+        let level = self.states.len() - 1;
+        if let Some(slot) = self.resolve_local(level, &name.value) {
+            self.emit(Op::GetLocal(slot));
+        } else if let Some(index) = self.resolve_upvalue(level, &name.value)? {
+            self.emit(Op::GetUpvalue(index));
+        } else {
+            let slot = self.globals.slot(&name.value);
+            self.emit(Op::GetGlobal(slot));
+        }
+        Ok(())
+    }
+
+    fn set_variable(&mut self, name: &Token) -> Result<(), Error> {
+        self.mark(name);
+        let level = self.states.len() - 1;
+        if let Some(slot) = self.resolve_local(level, &name.value) {
+            self.emit(Op::SetLocal(slot));
+        } else if let Some(index) = self.resolve_upvalue(level, &name.value)? {
+            self.emit(Op::SetUpvalue(index));
+        } else {
+            let slot = self.globals.slot(&name.value);
+            self.emit(Op::SetGlobal(slot));
+        }
+        Ok(())
+    }
+
+    // leaves the `try` blocks above `outer` on the way to a jump out of them, running their
+    // `finally`s innermost first. each `finally` is compiled as if the blocks it's in were
+    // all that's open, since it runs after its own handler has gone.
+    fn leave_tries(&mut self, outer: usize) -> Result<(), Error> {
+        let tries = self.state().tries.clone();
+        for i in (outer..tries.len()).rev() {
+            self.state().tries.truncate(i);
+            if tries[i].handler {
+                self.emit(Op::EndTry);
+            }
+            if let Some(finally) = &tries[i].finally {
+                self.statement(finally)?;
+            }
+        }
+        self.state().tries = tries;
+        Ok(())
+    }
+
+    // drops the locals deeper than `depth` without forgetting them, for a jump out of their scope
+    fn discard_locals(&mut self, depth: usize) {
+        let state = self.state();
+        let count = state.locals.iter().rev().take_while(|l| l.depth > depth).count();
+        for _ in 0..count {
+            state.chunk.code.push(Op::CloseUpvalue);
+            state.chunk.sites.push(state.site);
+        }
+    }
+
+    fn block(&mut self, nodes: &[Node]) -> Result<(), Error> {
+        self.begin_scope();
+        for node in nodes {
+            self.statement(node)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    fn statement(&mut self, node: &Node) -> Result<(), Error> {
+        match node {
+            Node::Block { body, .. } => self.block(body)?,
+            Node::Declare { name, value, .. } => {
+                let target = self.target(name)?;
+                self.expression(value)?;
+                self.store(target, name);
+            },
+            Node::Assign { name, value, .. } => {
+                self.expression(value)?;
+                self.set_variable(name)?;
+            },
+            Node::If { condition, body, else_block, .. } => {
+                self.reserve(node)?;
+                self.expression(condition)?;
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                self.statement(body)?;
+                match else_block {
+                    Some(else_block) => {
+                        let to_end = self.emit(Op::Jump(0));
+                        self.patch(to_else);
+                        self.statement(else_block)?;
+                        self.patch(to_end);
+                    },
+                    None => self.patch(to_else)
+                }
+            },
+            Node::While { condition, body, increment, .. } => {
+                self.reserve(node)?;
+                let start = self.state().chunk.code.len();
+                self.expression(condition)?;
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.begin_loop();
+                self.statement(body)?;
+                let state = self.state();
+                let done = state.loops.pop().unwrap();
+                let next = state.chunk.code.len();
+                for at in done.continues {
+                    self.patch_to(at, next);
+                }
+                if let Some(increment) = increment {
+                    self.statement(increment)?;
+                }
+                self.emit(Op::Jump(start as u32));
+                self.patch(exit);
+                for at in done.breaks {
+                    self.patch(at);
+                }
+            },
+            Node::ForIn { variable, iterable, body, .. } => {
+                self.expression(iterable)?;
+                self.mark(variable);
+                self.emit(Op::IterStart);
+                let start = self.emit(Op::IterNext(0));
+
+                // the variable is a new local every time round, so closures capture each value
+                self.begin_loop();
+                self.begin_scope();
+                self.add_local(&variable.value)?;
+                self.statement(body)?;
+                self.end_scope();
+
+                let done = self.state().loops.pop().unwrap();
+                for at in done.continues {
+                    self.patch_to(at, start);
+                }
+                self.emit(Op::Jump(start as u32));
+                self.patch(start);
+                for at in done.breaks {
+                    self.patch(at);
+                }
+                self.emit(Op::IterEnd);
+            },
+            Node::Break { keyword, .. } | Node::Continue { keyword, .. } => {
+                let (depth, tries) = match self.state().loops.last() {
+                    Some(l) => (l.depth, l.tries),
+                    None => return Err(Error::Syntax(Diagnostic::at("E0200", format!("Cannot use '{}' outside of a loop.", keyword.value), keyword)))
+                };
+                self.mark(keyword);
+                self.leave_tries(tries)?;
+                self.discard_locals(depth);
+                let jump = self.emit(Op::Jump(0));
+                let l = self.state().loops.last_mut().unwrap();
+                if keyword._type == TokenType::Break {
+                    l.breaks.push(jump);
+                } else {
+                    l.continues.push(jump);
+                }
+            },
+            Node::DeclareFn { name, args, body, .. } => {
+                // declared first, so the function can call itself
+                let target = self.target(name)?;
+                self.function(name, args, body, Kind::Function)?;
+                self.store(target, name);
+            },
+            Node::Return { keyword, value, .. } => {
+                // like the resolver, a call that's returned straight away takes over the frame,
+                // unless a `try` around it still has something to do once it's finished
+                let state = self.states.last().unwrap();
+                let tail = state.trying == 0 && matches!(state.kind, Kind::Function | Kind::Method);
+                match &**value {
+                    Node::FnCall { name, args, kwargs, paren, .. } if tail => self.call(name, args, kwargs, paren, true)?,
+                    value => self.expression(value)?
+                }
+                // any `finally` on the way out runs with the value still on the stack
+                self.add_local("")?;
+                self.leave_tries(0)?;
+                self.state().locals.pop();
+                self.mark(keyword);
+                self.emit(Op::Return);
+            },
+            Node::Throw { keyword, value, .. } => {
+                self.expression(value)?;
+                self.mark(keyword);
+                self.emit(Op::Throw);
+            },
+            Node::Try { body, catch, finally, .. } => self.try_statement(body, catch, finally)?,
+            Node::Class { name, superclass, methods, .. } => self.class(name, superclass, methods)?,
+            Node::Set { object, name, value, .. } => {
+                self.expression(object)?;
+                self.expression(value)?;
+                self.mark(name);
+                self.emit(Op::SetProperty);
+            },
+            Node::SetIndex { object, index, value, bracket, .. } => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.expression(value)?;
+                self.mark(bracket);
+                self.emit(Op::SetIndex);
+            },
+            expr => {
+                self.expression(expr)?;
+                self.emit(Op::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, name: &Node, args: &[Node], kwargs: &[(Token, Node)], paren: &Token, tail: bool) -> Result<(), Error> {
+        self.expression(name)?;
+        for arg in args {
+            self.expression(arg)?;
+        }
+        for (_, value) in kwargs {
+            self.expression(value)?;
+        }
+        self.mark(paren);
+        self.limit(args.len(), u8::MAX as usize, "arguments in a call")?;
+        let argc = args.len() as u8;
+        if kwargs.is_empty() {
+            self.emit(if tail { Op::TailCall(argc) } else { Op::Call(argc) });
+        } else {
+            let keywords = &mut self.state().chunk.keywords;
+            keywords.push(kwargs.iter().map(|(k, _)| k.clone()).collect());
+            let index = keywords.len() - 1;
+            self.limit(index, u16::MAX as usize, "calls with keyword arguments")?;
+            let index = index as u16;
+            self.emit(if tail { Op::TailCallKw(argc, index) } else { Op::CallKw(argc, index) });
+        }
+        Ok(())
+    }
+
+    fn begin_loop(&mut self) {
+        let state = self.state();
+        let (depth, tries) = (state.depth, state.tries.len());
+        state.loops.push(Loop { depth, tries, breaks: vec![], continues: vec![] });
+    }
+
+    // The handler gets the error on top of the stack. A `finally` is compiled once for
+    // every way out of the statement: falling out the bottom, an error nothing caught (or
+    // one from the `catch` itself), and any `return`, `break` or `continue` on the way.
+    fn try_statement(&mut self, body: &Node, catch: &Option<(Token, Box<Node>)>, finally: &Option<Box<Node>>) -> Result<(), Error> {
+        self.state().trying += 1;
+        let finally_node = finally.as_ref().map(|f| (**f).clone());
+        let handler = self.emit(Op::Try(0));
+        self.state().tries.push(TryBlock { finally: finally_node.clone(), handler: true });
+        self.statement(body)?;
+        self.state().tries.pop();
+        self.emit(Op::EndTry);
+        let mut to_end = vec![self.emit(Op::Jump(0))];
+        self.patch(handler);
+
+        match catch {
+            Some((name, handler_body)) => {
+                let rethrow = finally.as_ref().map(|_| self.emit(Op::Try(0)));
+                self.state().tries.push(TryBlock { finally: finally_node, handler: rethrow.is_some() });
+                self.begin_scope();
+                self.add_local(&name.value)?;
+                self.statement(handler_body)?;
+                let captured = self.state().locals.last().unwrap().captured;
+                self.end_scope();
+                self.state().tries.pop();
+
+                if let (Some(rethrow), Some(finally)) = (rethrow, finally) {
+                    self.emit(Op::EndTry);
+                    to_end.push(self.emit(Op::Jump(0)));
+                    // the catch's own error is on top of the one it caught
+                    self.patch(rethrow);
+                    self.emit(Op::Pop);
+                    self.emit(if captured { Op::CloseUpvalue } else { Op::Pop });
+                    self.statement(finally)?;
+                    self.emit(Op::Rethrow);
+                }
+            },
+            None => {
+                self.emit(Op::Pop);
+                if let Some(finally) = finally {
+                    self.statement(finally)?;
+                }
+                self.emit(Op::Rethrow);
+            }
+        }
+
+        for at in to_end {
+            self.patch(at);
+        }
+        if let Some(finally) = finally {
+            self.statement(finally)?;
+        }
+        self.state().trying -= 1;
+        Ok(())
+    }
+
+    // Methods close over a scope holding the superclass as `super`, and the class is only
+    // stored once they've all been made, like in the tree-walker.
+    fn class(&mut self, name: &Token, superclass: &Option<Box<Node>>, methods: &Vec<Node>) -> Result<(), Error> {
+        let target = self.target(name)?;
+        if let Target::New(_) = target {
+            self.emit(Op::None); // holds the class's slot until it exists
+        }
+
+        if let Some(superclass) = superclass {
+            self.expression(superclass)?;
+            if let Node::Variable { name, .. } = &**superclass {
+                self.mark(name);
+            }
+            self.emit(Op::Inherit);
+            self.begin_scope();
+            self.add_local("super")?;
+        }
+
+        for method in methods {
+            if let Node::DeclareFn { name, args, body, .. } = method {
+                let kind = if name.value == "init" { Kind::Initializer } else { Kind::Method };
+                self.function(name, args, body, kind)?;
+            }
+        }
+        self.limit(methods.len(), u16::MAX as usize, "methods")?;
+        self.mark(name);
+        self.emit(Op::Class(methods.len() as u16, superclass.is_some()));
+
+        match target {
+            Target::New(slot) => { self.emit(Op::SetLocal(slot)); },
+            target => self.store(target, name)
+        }
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    // compiles a function and leaves a closure of it on the stack
+    fn function(&mut self, name: &Token, params: &[Param], body: &Node, kind: Kind) -> Result<(), Error> {
+        self.states.push(State::new(name.clone(), params.to_vec(), kind));
+        self.begin_scope();
+
+        // every argument is in its slot before the body starts. defaults fill in the ones
+        // that weren't given, seeing only the parameters before them.
+        for (i, param) in params.iter().enumerate() {
+            if let Some(default) = &param.default {
+                let skip = self.emit(Op::JumpIfBound(i as u16 + 1, 0));
+                self.expression(default)?;
+                self.emit(Op::SetLocal(i as u16 + 1));
+                self.patch(skip);
+            }
+            self.add_local(&param.name.value)?;
+        }
+
+        match body {
+            Node::Block { body, .. } => self.block(body)?,
+            body => self.statement(body)?
+        }
+        self.emit(Op::None);
+        self.emit(Op::Return);
+
+        let proto = self.finish();
+        let functions = &mut self.state().chunk.functions;
+        functions.push(Rc::new(proto));
+        let index = functions.len() as u32 - 1;
+        self.emit(Op::Closure(index));
+        Ok(())
+    }
+
+    fn expression(&mut self, node: &Node) -> Result<(), Error> {
+        match node {
+            Node::Literal { value, .. } => {
+                match value {
+                    Literal::Number(n) => {
+                        let constant = self.constant(Object::Number(*n))?;
+                        self.emit(Op::Constant(constant));
+                    },
+                    Literal::String(s) => {
+                        let constant = self.constant(Object::String(s.as_str().into()))?;
+                        self.emit(Op::Constant(constant));
+                    },
+                    Literal::Bool(true) => { self.emit(Op::True); },
+                    Literal::Bool(false) => { self.emit(Op::False); },
+                    Literal::None => { self.emit(Op::None); }
+                }
+            },
+            Node::BinaryOperator { left, operator, right, .. } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.mark(operator);
+                let op = match operator._type {
+                    TokenType::Plus => Op::Add,
+                    TokenType::Minus => Op::Subtract,
+                    TokenType::Multiply => Op::Multiply,
+                    TokenType::Divide => Op::Divide,
+                    TokenType::Greater => Op::Greater,
+                    TokenType::Less => Op::Less,
+                    TokenType::GreraterEqual => Op::GreaterEqual,
+                    TokenType::LessEqual => Op::LessEqual,
+                    TokenType::Equal => Op::Equal,
+                    TokenType::NotEqual => Op::NotEqual,
+                    TokenType::In => Op::In,
+                    _ => return Err(Error::Syntax(Diagnostic::at("E0400", format!("The vm has no instruction for '{}'.", operator.value), operator)))
+                };
+                self.emit(op);
+            },
+            Node::UnaryOperator { operator, child, .. } => {
+                self.expression(child)?;
+                self.mark(operator);
+                self.emit(if operator._type == TokenType::Not { Op::Not } else { Op::Negate });
+            },
+            Node::Logical { left, operator, right, .. } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.mark(operator);
+                self.emit(if operator._type == TokenType::Or { Op::Or } else { Op::And });
+            },
+            Node::Variable { name, .. } => self.get_variable(name)?,
+            Node::This { keyword, .. } => self.get_variable(keyword)?,
+            Node::Super { keyword, method, .. } => {
+                let this = Token { value: "this".to_string(), ..keyword.clone() };
+                self.get_variable(&this)?;
+                self.get_variable(keyword)?;
+                self.mark(method);
+                self.emit(Op::GetSuper);
+            },
+            Node::Lambda { keyword, args, body, .. } => self.function(keyword, args, body, Kind::Function)?,
+            Node::FnCall { name, args, kwargs, paren, .. } => self.call(name, args, kwargs, paren, false)?,
+            Node::Get { object, name, .. } => {
+                self.expression(object)?;
+                self.mark(name);
+                self.emit(Op::GetProperty);
+            },
+            Node::Interpolation { parts, .. } => {
+                for part in parts {
+                    self.expression(part)?;
+                }
+                self.emit(Op::Interpolate(parts.len() as u32));
+            },
+            Node::List { items, .. } => {
+                for item in items {
+                    self.expression(item)?;
+                }
+                self.emit(Op::List(items.len() as u32));
+            },
+            Node::Map { entries, brace, .. } => {
+                for (key, value) in entries {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+                self.mark(brace);
+                self.emit(Op::Map(entries.len() as u32));
+            },
+            Node::Range { start, end, step, inclusive, operator, .. } => {
+                self.expression(start)?;
+                self.expression(end)?;
+                if let Some(step) = step {
+                    self.expression(step)?;
+                }
+                self.mark(operator);
+                self.emit(Op::Range(*inclusive, step.is_some()));
+            },
+            Node::Index { object, index, bracket, .. } => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.mark(bracket);
+                self.emit(Op::Index);
+            },
+            Node::Slice { object, start, end, bracket, .. } => {
+                self.expression(object)?;
+                if let Some(start) = start {
+                    self.expression(start)?;
+                }
+                if let Some(end) = end {
+                    self.expression(end)?;
+                }
+                self.mark(bracket);
+                self.emit(Op::Slice(start.is_some(), end.is_some()));
+            },
+            statement => {
+                // only expressions get here, but a statement in their place still leaves a value
+                self.statement(statement)?;
+                self.emit(Op::None);
+            }
+        }
+        Ok(())
+    }
+}
+
+enum Target {
+    Global(u32),
+    Existing(u16),
+    New(u16)
+}
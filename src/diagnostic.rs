@@ -0,0 +1,131 @@
+use std::fmt::Display;
+use crate::token::{Token, TokenType};
+
+// Error codes, so a failure can be looked up no matter how its message is worded.
+//
+//   E00xx  lexing: E0001 unknown character, E0002 unterminated string or comment,
+//          E0003 bad escape sequence, E0004 bad number, E0005 empty `{}` in a string
+//   E01xx  parsing: E0100 unexpected token, E0101 missing separator, E0102 invalid
+//          assignment target, E0103 bad parameter list, E0104 bad argument list,
+//          E0105 unclosed bracket, E0106 try without catch or finally
+//   E02xx  resolving: E0200 `break`/`continue` outside a loop, E0201 `this` outside a
+//          class, E0202 `super` without a superclass, E0203 class inheriting from itself,
+//          E0204 `return` outside a function, E0205 returning a value from `init`,
+//          E0206 reading a variable in its own declaration
+//   E03xx  runtime, one per `ErrorKind`
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub label: String, // "error", or the kind of a runtime error
+    pub message: String,
+    pub line: usize, // 0 when there's no location to point at
+    pub column: usize,
+    pub length: usize, // how many characters to underline
+    pub notes: Vec<String>,
+    pub help: Option<String>
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, message: String, line: usize, column: usize) -> Self {
+        Self { code, label: "error".to_string(), message, line, column, length: 1, notes: vec![], help: None }
+    }
+
+    // underlines the whole of `token`
+    pub fn at(code: &'static str, message: String, token: &Token) -> Self {
+        Self { length: width(token), ..Self::new(code, message, token.line, token.column) }
+    }
+
+    pub fn note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn help(mut self, help: String) -> Self {
+        self.help = Some(help);
+        self
+    }
+
+    // rustc-style, quoting the line from `source` (when it's there) with the span underlined:
+    //
+    //   error[E0101]: Expected a separator after the statement.
+    //    --> main.nt:1:10
+    //     |
+    //   1 | let x = 5
+    //     |          ^
+    //     = help: add a `;` here
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let mut out = format!("\x1b[1;31m{}[{}]\x1b[0m\x1b[1m: {}\x1b[0m\n", self.label, self.code, self.message);
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        let text = source.lines().nth(self.line.max(1) - 1).filter(|_| self.line != 0);
+        // columns count characters, so find the byte the carets start at
+        let start = text.map(|text| text.char_indices().nth(self.column.max(1) - 1).map_or(text.len(), |(i, _)| i));
+        if self.line != 0 {
+            out.push_str(&format!("{}\x1b[1;34m-->\x1b[0m {}:{}:{}\n", gutter, path, self.line, self.column));
+        }
+        if let (Some(text), Some(start)) = (text, start) {
+            // tabs are kept so the carets line up however they're displayed
+            let padding: String = text.get(..start).unwrap_or("").chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let rest = text.get(start..).unwrap_or("").chars().count();
+            let carets = "^".repeat(self.length.min(rest).max(1));
+
+            out.push_str(&format!("{} \x1b[1;34m|\x1b[0m\n", gutter));
+            out.push_str(&format!("\x1b[1;34m{} |\x1b[0m {}\n", self.line, text));
+            out.push_str(&format!("{} \x1b[1;34m|\x1b[0m {}\x1b[1;31m{}\x1b[0m\n", gutter, padding, carets));
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("{} \x1b[1;34m=\x1b[0m \x1b[1mnote\x1b[0m: {}\n", gutter, note));
+        }
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{} \x1b[1;34m=\x1b[0m \x1b[1mhelp\x1b[0m: {}\n", gutter, help));
+        }
+        out.trim_end().to_string()
+    }
+}
+
+// the plain one-line form, for when there's no source to quote
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: {}", self.label, self.code, self.message)?;
+        if self.line != 0 {
+            write!(f, " [{}:{}]", self.line, self.column)?;
+        }
+        Ok(())
+    }
+}
+
+// how many characters `token` takes up on its first line
+pub fn width(token: &Token) -> usize {
+    match token._type {
+        TokenType::Eof => 1,
+        _ => token.value.lines().next().map_or(1, |l| l.chars().count().max(1))
+    }
+}
+
+// the candidate closest to `name`, if any is close enough to be a likely typo
+pub fn similar<'a>(name: &str, candidates: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .map(|c| (distance(name, c), c))
+        .filter(|(d, c)| *d <= limit && c.as_str() != name)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+// levenshtein distance
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb { previous } else { 1 + previous.min(row[j]).min(current) };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
@@ -1,7 +1,165 @@
-use crate::interpreter::Object;
+use std::fmt::Display;
+use crate::{interpreter::{Object, repr}, function::TailCall, token::Token, diagnostic::{Diagnostic, width}};
 
 pub enum Error {
-    Runtime(String),
-    Syntax(String),
-    Return(Object)
-}
\ No newline at end of file
+    Runtime(RuntimeError),
+    Syntax(Diagnostic),
+    Parse(Vec<Diagnostic>), // every syntax error in a file, when there's more than one
+    Return(Object),
+    TailCall(Box<TailCall>), // `return f(...)`, which the function's caller makes in its place
+    Break,
+    Continue
+}
+
+impl Error {
+    // a runtime error pointing at `token`
+    pub fn at(kind: ErrorKind, token: &Token, message: String) -> Error {
+        Error::Runtime(RuntimeError { length: width(token), ..RuntimeError::new(kind, message, token.line, token.column) })
+    }
+
+    // a runtime error with no location yet, e.g. from inside a native function.
+    // the call it escapes from fills one in.
+    pub fn new(kind: ErrorKind, message: String) -> Error {
+        Error::Runtime(RuntimeError::new(kind, message, 0, 0))
+    }
+
+    // `throw value` at `keyword`
+    pub fn thrown(value: Object, keyword: &Token) -> Error {
+        let message = match &value {
+            Object::Instance(instance) => match instance.borrow().fields.get("message") {
+                Some(Object::String(s)) => s.to_string(),
+                Some(other) => repr(other),
+                None => value.to_string()
+            },
+            other => repr(other)
+        };
+        Error::Runtime(RuntimeError { value: Some(value), ..RuntimeError::new(ErrorKind::Thrown, message, keyword.line, keyword.column) })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    Name, // unknown variables
+    Type, // a value of the wrong type for an operation
+    Attribute, // missing properties and methods
+    Index,
+    Key,
+    Value, // right type, wrong value (a zero step, popping an empty list)
+    Argument, // calls with the wrong arguments
+    Thrown // raised by a `throw` statement
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ErrorKind::Name => "NameError",
+            ErrorKind::Type => "TypeError",
+            ErrorKind::Attribute => "AttributeError",
+            ErrorKind::Index => "IndexError",
+            ErrorKind::Key => "KeyError",
+            ErrorKind::Value => "ValueError",
+            ErrorKind::Argument => "ArgumentError",
+            ErrorKind::Thrown => "Error"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ErrorKind {
+    fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::Name => "E0301",
+            ErrorKind::Type => "E0302",
+            ErrorKind::Attribute => "E0303",
+            ErrorKind::Index => "E0304",
+            ErrorKind::Key => "E0305",
+            ErrorKind::Value => "E0306",
+            ErrorKind::Argument => "E0307",
+            ErrorKind::Thrown => "E0308"
+        }
+    }
+}
+
+// a call the error passed through on its way out: the function and where it was called from
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: String,
+    pub line: usize,
+    pub column: usize
+}
+
+#[derive(Clone)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub line: usize, // 0 when the location isn't known
+    pub column: usize,
+    pub length: usize, // of the token the error points at
+    pub help: Option<String>,
+    pub trace: Vec<Frame>, // innermost call first
+    pub value: Option<Object> // what was thrown, for errors from `throw`
+}
+
+impl RuntimeError {
+    fn new(kind: ErrorKind, message: String, line: usize, column: usize) -> Self {
+        Self { kind, message, line, column, length: 1, help: None, trace: vec![], value: None }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(self.kind.code(), self.message.clone(), self.line, self.column);
+        diagnostic.label = self.kind.to_string();
+        diagnostic.length = self.length;
+        diagnostic.help = self.help.clone();
+        diagnostic
+    }
+
+    // the traceback, with the error itself quoted from `source`
+    pub fn render(&self, source: &str, path: &str) -> String {
+        format!("{}{}", self.frames(), self.diagnostic().render(source, path))
+    }
+
+    // records that the error escaped from a call to `name` at `site`
+    pub fn unwind(&mut self, name: &str, site: &Token) {
+        if self.line == 0 {
+            self.line = site.line;
+            self.column = site.column;
+        }
+        self.trace.push(Frame { name: name.to_string(), line: site.line, column: site.column });
+    }
+
+    // Python-style, with the outermost call first and runs of the same frame folded up
+    pub fn traceback(&self) -> String {
+        format!("{}{}", self.frames(), self)
+    }
+
+    fn frames(&self) -> String {
+        let mut out = String::new();
+        if !self.trace.is_empty() {
+            out.push_str("Traceback (most recent call last):\n");
+            let frames: Vec<&Frame> = self.trace.iter().rev().collect();
+            let mut i = 0;
+            while i < frames.len() {
+                let frame = frames[i];
+                let repeats = frames[i..].iter()
+                    .take_while(|f| f.name == frame.name && f.line == frame.line && f.column == frame.column)
+                    .count();
+                out.push_str(&format!("  in {}() called at [{}:{}]\n", frame.name, frame.line, frame.column));
+                if repeats > 1 {
+                    out.push_str(&format!("  [previous line repeated {} more times]\n", repeats - 1));
+                }
+                i += repeats;
+            }
+        }
+        out
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)?;
+        if self.line != 0 {
+            write!(f, " [{}:{}]", self.line, self.column)?;
+        }
+        Ok(())
+    }
+}
@@ -1,55 +1,259 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{fmt::Display, rc::Rc, cell::RefCell};
 
 use crate::{
     token::Token,
-    interpreter::{Interpreter, Object},
-    ast::Node,
-    error::Error
+    interpreter::{Interpreter, Object, Environment},
+    ast::{Node, Param},
+    error::{Error, ErrorKind},
+    vm::Closure,
+    heap
 };
 
+// Something that can run a function: the tree-walker or the vm. Whatever calls back into
+// the program from outside it, like the iterator protocol, goes through this.
+pub trait Call {
+    fn invoke(&mut self, f: &mut Function, args: Vec<Object>) -> Result<Object, Error>;
+}
+
 #[derive(Clone)]
 pub enum Function {
     UserDefined {
-        args: Vec<Token>,
-        body: Node,
-        name: Token
+        args: Rc<Vec<Param>>,
+        body: Rc<Node>, // shared with the declaration, and every other function made from it
+        name: Token,
+        closure: Vec<Environment>, // the environment chain the function was declared in
+        is_initializer: bool, // `init` methods always give back `this`
+        bound: bool // a method bound to an instance, which `closure` ends with a scope holding
     },
     Native {
         arg_len: usize,
+        optional: usize, // how many of the last arguments can be left off
         body: Box<fn(&Vec<Object>) -> Result<Object, Error>>,
-        name: String
+        name: String,
+        this: Option<Box<Object>> // the instance a native method was bound to, passed in before the arguments
+    },
+    Compiled { // bytecode, which only the vm can run
+        closure: Rc<Closure>,
+        this: Option<Box<Object>> // the instance a method was bound to
     }
 }
 
 impl Function {
-    pub fn call(&mut self, interpreter: &mut Interpreter, actual_args: Vec<Object>) -> Result<Object, Error> {
+    // a copy of the method whose body sees `instance` as `this`
+    pub fn bind(&self, instance: Object) -> Function {
         match self {
-            Function::UserDefined { args, body, .. } => {
-                interpreter.environments.push(HashMap::new());
-        
-                for (expected, actual) in args.iter().zip(actual_args.iter()) {
-                    let enviro = interpreter.environments.last_mut().unwrap();
-                    enviro.insert(expected.value.clone(), actual.clone());
+            Function::UserDefined { args, body, name, closure, is_initializer, .. } => {
+                let this: Environment = Rc::new(RefCell::new(vec![instance]));
+                heap::capture(std::slice::from_ref(&this));
+                let mut closure = closure.clone();
+                closure.push(this);
+                Function::UserDefined {
+                    args: args.clone(),
+                    body: body.clone(),
+                    name: name.clone(),
+                    closure,
+                    is_initializer: *is_initializer,
+                    bound: true
                 }
-        
-                match interpreter.traverse(&body) {
-                    Err(Error::Runtime(v)) => return Err(Error::Runtime(v)),
-                    Err(Error::Return(v)) => {
-                        interpreter.environments.pop();
-                        return Ok(v)
-                    },
-                    _ => {
-                        interpreter.environments.pop();
-                        return Ok(Object::None)
+            },
+            Function::Native { arg_len, optional, body, name, .. } => Function::Native {
+                arg_len: *arg_len,
+                optional: *optional,
+                body: body.clone(),
+                name: name.clone(),
+                this: Some(Box::new(instance))
+            },
+            Function::Compiled { closure, .. } => Function::Compiled { closure: closure.clone(), this: Some(Box::new(instance)) }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Function::UserDefined { name, .. } => &name.value,
+            Function::Native { name, .. } => name,
+            Function::Compiled { closure, .. } => &closure.proto.name.value
+        }
+    }
+
+    // `actual_args` are positional; `kwargs` are `name: value` pairs from the call site
+    pub fn call(&mut self, interpreter: &mut Interpreter, actual_args: Vec<Object>, kwargs: Vec<(Token, Object)>) -> Result<Object, Error> {
+        let fn_name = self.name().to_string();
+        match self {
+            Function::UserDefined { args, .. } => {
+                let values = arrange(&fn_name, args, actual_args, kwargs)?;
+                let mut result = self.enter(interpreter, values);
+                // tail calls are made here, one after another, rather than inside each other,
+                // so recursing through them doesn't use up the stack. an error from one is
+                // traced to the last of them, since the ones before it have already finished
+                while let Err(Error::TailCall(call)) = result {
+                    let TailCall { f, values, paren } = *call;
+                    result = f.enter(interpreter, values);
+                    if let Err(Error::Runtime(e)) = &mut result {
+                        e.unwind(f.name(), &paren);
                     }
-                };
+                }
+                result
             },
 
-            Function::Native { body, .. } => {
-                Ok(body(&actual_args)?)
+            Function::Native { .. } => self.native(actual_args, kwargs),
+
+            Function::Compiled { .. } => {
+                Err(Error::new(ErrorKind::Type, format!("{}() was compiled for the vm and can't be called from the tree-walker.", fn_name)))
             }
         }
     }
+
+    // runs a native function, which only takes positional arguments
+    pub fn native(&self, mut actual_args: Vec<Object>, kwargs: Vec<(Token, Object)>) -> Result<Object, Error> {
+        let (fn_name, body, arg_len, optional, this) = match self {
+            Function::Native { name, body, arg_len, optional, this } => (name, body, *arg_len, *optional, this),
+            _ => return Ok(Object::None)
+        };
+        if let Some((name, _)) = kwargs.first() {
+            return Err(Error::at(ErrorKind::Argument, name, format!("{}() doesn't take keyword arguments, but got '{}'.", fn_name, name.value)))
+        }
+        let given = actual_args.len();
+        if given > arg_len || given + optional < arg_len {
+            let (bound, n) = match optional {
+                0 => ("", arg_len),
+                _ if given > arg_len => ("at most ", arg_len),
+                _ => ("at least ", arg_len - optional)
+            };
+            return Err(Error::new(ErrorKind::Argument, format!("{}() takes {}{} {}, but {} {} given.", fn_name, bound, n, plural(n), given, was(given))))
+        }
+        if let Some(this) = this {
+            actual_args.insert(0, (**this).clone());
+        }
+        body(&actual_args)
+    }
+
+    // whether both are the same function. methods are bound again every time they're looked
+    // up, so two of those are the same if they're the same method bound to the same instance
+    pub fn is(&self, other: &Function) -> bool {
+        match (self, other) {
+            (Function::UserDefined { body: a, closure: x, bound, .. }, Function::UserDefined { body: b, closure: y, bound: other_bound, .. }) => {
+                let shared = if *bound { x.len() - 1 } else { x.len() };
+                Rc::ptr_eq(a, b) && bound == other_bound && x.len() == y.len()
+                    && x[..shared].iter().zip(&y[..shared]).all(|(x, y)| Rc::ptr_eq(x, y))
+                    && (!bound || x[shared].borrow()[0].equals(&y[shared].borrow()[0]))
+            },
+            (Function::Native { name: a, this: x, .. }, Function::Native { name: b, this: y, .. }) => a == b && same_this(x, y),
+            (Function::Compiled { closure: a, this: x }, Function::Compiled { closure: b, this: y }) => Rc::ptr_eq(a, b) && same_this(x, y),
+            _ => false
+        }
+    }
+
+    // runs the body of a user-defined function, with its arguments already matched up with
+    // its parameters by `arrange`. anything else has no body to run
+    fn enter(&self, interpreter: &mut Interpreter, values: Vec<Option<Object>>) -> Result<Object, Error> {
+        let (params, body, closure, is_initializer) = match self {
+            Function::UserDefined { args, body, closure, is_initializer, .. } => (args, body, closure, *is_initializer),
+            _ => return Ok(Object::None)
+        };
+        // run the body against the chain the function was declared in, not the caller's
+        let enviro: Environment = Rc::new(RefCell::new(Vec::with_capacity(params.len())));
+        let mut environments = closure.clone();
+        environments.push(enviro.clone());
+        let caller = std::mem::replace(&mut interpreter.environments, environments);
+
+        let result = bind(params, interpreter, &enviro, values)
+            .and_then(|_| interpreter.traverse(body));
+        interpreter.environments = caller;
+
+        match result {
+            Err(Error::Return(_)) | Ok(_) if is_initializer => {
+                Ok(closure.last().unwrap().borrow()[0].clone()) // `this`
+            },
+            Err(Error::Return(v)) => Ok(v),
+            Err(e) => Err(e),
+            Ok(_) => Ok(Object::None)
+        }
+    }
+}
+
+// a call in tail position, waiting to be made once the function it's in has returned
+pub struct TailCall {
+    pub f: Function,
+    pub values: Vec<Option<Object>>, // the arguments, as `arrange` matched them up
+    pub paren: Token
+}
+
+fn same_this(a: &Option<Box<Object>>, b: &Option<Box<Object>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.equals(b),
+        (a, b) => a.is_none() && b.is_none()
+    }
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 { "argument" } else { "arguments" }
+}
+
+pub fn was(n: usize) -> &'static str {
+    if n == 1 { "was" } else { "were" }
+}
+
+// matches the arguments of a call up with `params`, giving one value per parameter.
+// parameters left to their default are `None`, for the caller to evaluate.
+pub fn arrange(fn_name: &str, params: &[Param], actual_args: Vec<Object>, mut kwargs: Vec<(Token, Object)>) -> Result<Vec<Option<Object>>, Error> {
+    let variadic = params.last().is_some_and(|p| p.variadic);
+    let positional = if variadic { params.len() - 1 } else { params.len() };
+    if actual_args.len() > positional && !variadic {
+        let at_most = if params.iter().any(|p| p.default.is_some()) { "at most " } else { "" };
+        return Err(Error::new(ErrorKind::Argument, format!(
+            "{}() takes {}{} {}, but {} {} given.",
+            fn_name, at_most, positional, plural(positional), actual_args.len(), was(actual_args.len())
+        )))
+    }
+
+    if let Some((k, _)) = kwargs.iter().find(|(k, _)| !params.iter().any(|p| p.name.value == k.value)) {
+        return Err(Error::at(ErrorKind::Argument, k, format!("{}() got an unexpected keyword argument '{}'.", fn_name, k.value)))
+    }
+
+    let mut values = vec![];
+    let mut actual_args = actual_args.into_iter();
+    for (i, param) in params.iter().enumerate() {
+        let name = &param.name.value;
+        let keyword = kwargs.iter().position(|(k, _)| &k.value == name).map(|i| kwargs.remove(i));
+
+        let value = if param.variadic {
+            if let Some((k, _)) = keyword {
+                return Err(Error::at(ErrorKind::Argument, &k, format!("{}() can't take variadic parameter '{}' as a keyword argument.", fn_name, name)))
+            }
+            Some(heap::list(actual_args.by_ref().collect()))
+        } else if let Some(arg) = actual_args.next() {
+            if let Some((k, _)) = keyword {
+                return Err(Error::at(ErrorKind::Argument, &k, format!("{}() got multiple values for parameter '{}'.", fn_name, name)))
+            }
+            Some(arg)
+        } else if let Some((_, arg)) = keyword {
+            Some(arg)
+        } else if param.default.is_some() {
+            None
+        } else {
+            let missing: Vec<String> = params[i..].iter()
+                .filter(|p| p.default.is_none() && !p.variadic && !kwargs.iter().any(|(k, _)| k.value == p.name.value))
+                .map(|p| format!("'{}'", p.name.value))
+                .collect();
+            let (args, params) = if missing.len() == 1 { ("an argument", "parameter") } else { ("arguments", "parameters") };
+            return Err(Error::new(ErrorKind::Argument, format!("{}() is missing {} for {} {}.", fn_name, args, params, missing.join(", "))))
+        };
+        values.push(value);
+    }
+    Ok(values)
+}
+
+// binds the arguments of a call to `params` in `enviro`, which is already the innermost
+// scope, so defaults can see the parameters before them.
+fn bind(params: &[Param], interpreter: &mut Interpreter, enviro: &Environment, values: Vec<Option<Object>>) -> Result<(), Error> {
+    for (param, value) in params.iter().zip(values) {
+        let value = match value {
+            Some(value) => value,
+            None => interpreter.traverse(param.default.as_ref().unwrap())?
+        };
+        enviro.borrow_mut().push(value); // parameters are the first slots, in order
+    }
+    Ok(())
 }
 
 impl Display for Function {
@@ -60,6 +264,10 @@ impl Display for Function {
             },
             Function::Native { name, .. } => {
                 write!(f, "<native fn '{}'>", name)
+            },
+            Function::Compiled { closure, .. } => {
+                let name = &closure.proto.name;
+                write!(f, "<fn '{}' at [{}:{}]>", name.value, name.line, name.column)
             }
         }
     }
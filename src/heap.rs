@@ -0,0 +1,320 @@
+use std::{cell::RefCell, collections::{HashMap, HashSet}, rc::{Rc, Weak}};
+
+use crate::{
+    interpreter::{Object, Environment},
+    function::Function,
+    class::{Class, Instance},
+    map::Map,
+    vm::{Closure, Upvalue}
+};
+
+// Values on the heap are reference counted, which frees most of them as soon as the last
+// handle goes. What counting can't free is a cycle: a list holding itself, an instance whose
+// field points back at it, or a local function that closes over the scope it's stored in.
+//
+// So everything that could be part of a cycle is made here and tracked, and every so often
+// the tracked values are collected: for each one, the references held by other tracked
+// values are taken away from its count. Whatever's left over comes from outside the heap
+// (the interpreter's scopes, the vm's stack, a native halfway through running), which keeps
+// it, and everything it refers to, alive. The rest is only kept alive by itself, so those
+// values are emptied out, which breaks their cycles and lets counting free them.
+
+pub const THRESHOLD: usize = 1000;
+
+enum Handle {
+    List(Weak<RefCell<Vec<Object>>>), // scopes are lists too
+    Map(Weak<RefCell<Map>>),
+    Instance(Weak<RefCell<Instance>>),
+    Class(Weak<Class>),
+    Closure(Weak<Closure>),
+    Upvalue(Weak<RefCell<Upvalue>>)
+}
+
+// a tracked value that's still alive, for the length of a collection
+enum Value {
+    List(Rc<RefCell<Vec<Object>>>),
+    Map(Rc<RefCell<Map>>),
+    Instance(Rc<RefCell<Instance>>),
+    Class(Rc<Class>),
+    Closure(Rc<Closure>),
+    Upvalue(Rc<RefCell<Upvalue>>)
+}
+
+#[derive(Clone, Copy)]
+pub struct Stats {
+    pub tracked: usize,
+    pub allocated: usize, // since the last collection
+    pub collections: usize,
+    pub freed: usize,
+    pub threshold: usize
+}
+
+struct Heap {
+    handles: HashMap<usize, Handle>, // by address
+    survivors: usize, // how many values the last collection kept
+    stats: Stats
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap {
+        handles: HashMap::new(),
+        survivors: 0,
+        stats: Stats { tracked: 0, allocated: 0, collections: 0, freed: 0, threshold: THRESHOLD }
+    });
+}
+
+fn address<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+impl Handle {
+    fn upgrade(&self) -> Option<Value> {
+        Some(match self {
+            Handle::List(weak) => Value::List(weak.upgrade()?),
+            Handle::Map(weak) => Value::Map(weak.upgrade()?),
+            Handle::Instance(weak) => Value::Instance(weak.upgrade()?),
+            Handle::Class(weak) => Value::Class(weak.upgrade()?),
+            Handle::Closure(weak) => Value::Closure(weak.upgrade()?),
+            Handle::Upvalue(weak) => Value::Upvalue(weak.upgrade()?)
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        match self {
+            Handle::List(weak) => weak.strong_count() > 0,
+            Handle::Map(weak) => weak.strong_count() > 0,
+            Handle::Instance(weak) => weak.strong_count() > 0,
+            Handle::Class(weak) => weak.strong_count() > 0,
+            Handle::Closure(weak) => weak.strong_count() > 0,
+            Handle::Upvalue(weak) => weak.strong_count() > 0
+        }
+    }
+}
+
+impl Value {
+    fn count(&self) -> usize {
+        match self {
+            Value::List(rc) => Rc::strong_count(rc),
+            Value::Map(rc) => Rc::strong_count(rc),
+            Value::Instance(rc) => Rc::strong_count(rc),
+            Value::Class(rc) => Rc::strong_count(rc),
+            Value::Closure(rc) => Rc::strong_count(rc),
+            Value::Upvalue(rc) => Rc::strong_count(rc)
+        }
+    }
+
+    // calls `visit` with the address of everything this holds a handle to. something that's
+    // being changed right now can't be looked into, which gives back false
+    fn refs(&self, visit: &mut dyn FnMut(usize)) -> bool {
+        match self {
+            Value::List(rc) => match rc.try_borrow() {
+                Ok(items) => items.iter().for_each(|item| object(item, visit)),
+                Err(_) => return false
+            },
+            Value::Map(rc) => match rc.try_borrow() {
+                Ok(map) => for (key, value) in map.entries() {
+                    object(key, visit);
+                    object(value, visit);
+                },
+                Err(_) => return false
+            },
+            Value::Instance(rc) => match rc.try_borrow() {
+                Ok(instance) => {
+                    visit(address(&instance.class));
+                    instance.fields.values().for_each(|field| object(field, visit));
+                },
+                Err(_) => return false
+            },
+            Value::Class(class) => {
+                if let Some(superclass) = &class.superclass {
+                    visit(address(superclass));
+                }
+                class.methods.values().for_each(|method| function(method, visit));
+            },
+            Value::Closure(closure) => closure.upvalues.iter().for_each(|upvalue| visit(address(upvalue))),
+            Value::Upvalue(rc) => match rc.try_borrow() {
+                Ok(upvalue) => if let Upvalue::Closed(value) = &*upvalue {
+                    object(value, visit);
+                },
+                Err(_) => return false
+            }
+        }
+        true
+    }
+
+    // takes out everything the value holds, into `junk`. classes and closures can't change,
+    // so a cycle always runs through something else that can be emptied
+    fn clear(&self, junk: &mut Vec<Object>) {
+        match self {
+            Value::List(rc) => if let Ok(mut items) = rc.try_borrow_mut() {
+                junk.append(&mut items);
+            },
+            Value::Map(rc) => if let Ok(mut map) = rc.try_borrow_mut() {
+                let map = std::mem::take(&mut *map);
+                junk.extend(map.entries().iter().flat_map(|(k, v)| [k.clone(), v.clone()]));
+            },
+            Value::Instance(rc) => if let Ok(mut instance) = rc.try_borrow_mut() {
+                junk.extend(std::mem::take(&mut instance.fields).into_values());
+            },
+            Value::Upvalue(rc) => if let Ok(mut upvalue) = rc.try_borrow_mut() {
+                if let Upvalue::Closed(value) = std::mem::replace(&mut *upvalue, Upvalue::Closed(Object::None)) {
+                    junk.push(value);
+                }
+            },
+            Value::Class(_) | Value::Closure(_) => {}
+        }
+    }
+}
+
+fn object(obj: &Object, visit: &mut dyn FnMut(usize)) {
+    match obj {
+        Object::List(rc) => visit(address(rc)),
+        Object::Map(rc) => visit(address(rc)),
+        Object::Instance(rc) => visit(address(rc)),
+        Object::Class(rc) => visit(address(rc)),
+        Object::Function(f) => function(f, visit),
+        _ => {}
+    }
+}
+
+fn function(f: &Function, visit: &mut dyn FnMut(usize)) {
+    match f {
+        Function::UserDefined { closure, .. } => closure.iter().for_each(|enviro| visit(address(enviro))),
+        Function::Compiled { closure, this } => {
+            visit(address(closure));
+            if let Some(this) = this {
+                object(this, visit);
+            }
+        },
+        Function::Native { this, .. } => if let Some(this) = this {
+            object(this, visit);
+        }
+    }
+}
+
+fn track(address: usize, handle: Handle) {
+    let due = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        if heap.handles.insert(address, handle).is_none() {
+            heap.stats.allocated += 1;
+        }
+        // waiting for as many new values as survived last time keeps collecting in proportion
+        // to how much there is to go through
+        let threshold = heap.stats.threshold;
+        threshold > 0 && heap.stats.allocated >= threshold.max(heap.survivors)
+    });
+    if due {
+        collect();
+    }
+}
+
+pub fn list(items: Vec<Object>) -> Object {
+    let rc = Rc::new(RefCell::new(items));
+    track(address(&rc), Handle::List(Rc::downgrade(&rc)));
+    Object::List(rc)
+}
+
+pub fn map(map: Map) -> Object {
+    let rc = Rc::new(RefCell::new(map));
+    track(address(&rc), Handle::Map(Rc::downgrade(&rc)));
+    Object::Map(rc)
+}
+
+pub fn instance(instance: Instance) -> Object {
+    let rc = Rc::new(RefCell::new(instance));
+    track(address(&rc), Handle::Instance(Rc::downgrade(&rc)));
+    Object::Instance(rc)
+}
+
+pub fn class(class: Class) -> Rc<Class> {
+    let rc = Rc::new(class);
+    track(address(&rc), Handle::Class(Rc::downgrade(&rc)));
+    rc
+}
+
+pub fn closure(closure: Closure) -> Rc<Closure> {
+    let rc = Rc::new(closure);
+    track(address(&rc), Handle::Closure(Rc::downgrade(&rc)));
+    rc
+}
+
+pub fn upvalue(upvalue: Upvalue) -> Rc<RefCell<Upvalue>> {
+    let rc = Rc::new(RefCell::new(upvalue));
+    track(address(&rc), Handle::Upvalue(Rc::downgrade(&rc)));
+    rc
+}
+
+// scopes only become part of the heap once a function closes over them. until then nothing
+// but the interpreter can refer to them, so they can't be in a cycle
+pub fn capture(environments: &[Environment]) {
+    for enviro in environments {
+        let address = address(enviro);
+        // a dead handle here belonged to a scope that's been freed and had its address reused
+        if !HEAP.with(|heap| heap.borrow().handles.get(&address).is_some_and(Handle::is_alive)) {
+            track(address, Handle::List(Rc::downgrade(enviro)));
+        }
+    }
+}
+
+// frees every value that's only kept alive by a cycle, giving back how many there were
+pub fn collect() -> usize {
+    let mut handles = HEAP.with(|heap| std::mem::take(&mut heap.borrow_mut().handles));
+    let values: HashMap<usize, Value> = handles.iter()
+        .filter_map(|(address, handle)| Some((*address, handle.upgrade()?)))
+        .collect();
+
+    // one of each count is the handle `values` holds
+    let mut outside: HashMap<usize, usize> = values.iter().map(|(address, value)| (*address, value.count() - 1)).collect();
+    let mut roots = vec![];
+    for (address, value) in &values {
+        let readable = value.refs(&mut |to| if let Some(count) = outside.get_mut(&to) {
+            *count = count.saturating_sub(1);
+        });
+        if !readable {
+            roots.push(*address);
+        }
+    }
+    roots.extend(outside.iter().filter(|(_, count)| **count > 0).map(|(address, _)| *address));
+
+    let mut reachable: HashSet<usize> = roots.iter().copied().collect();
+    while let Some(address) = roots.pop() {
+        values[&address].refs(&mut |to| if values.contains_key(&to) && reachable.insert(to) {
+            roots.push(to);
+        });
+    }
+
+    let mut junk = vec![];
+    let mut freed = 0;
+    for (address, value) in &values {
+        if !reachable.contains(address) {
+            value.clear(&mut junk);
+            freed += 1;
+        }
+    }
+    drop(values);
+    drop(junk); // the cycles are broken, so this frees them
+
+    handles.retain(|_, handle| handle.is_alive());
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        handles.extend(heap.handles.drain());
+        heap.handles = handles;
+        heap.survivors = heap.handles.len();
+        heap.stats.allocated = 0;
+        heap.stats.collections += 1;
+        heap.stats.freed += freed;
+    });
+    freed
+}
+
+pub fn stats() -> Stats {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        Stats { tracked: heap.handles.len(), ..heap.stats }
+    })
+}
+
+// how many values get made between collections. 0 stops them happening on their own
+pub fn set_threshold(threshold: usize) {
+    HEAP.with(|heap| heap.borrow_mut().stats.threshold = threshold);
+}
@@ -0,0 +1,175 @@
+use std::borrow::Cow;
+use rustyline::{
+    Helper,
+    Context,
+    Result,
+    completion::Completer,
+    hint::Hinter,
+    highlight::Highlighter,
+    validate::{Validator, ValidationContext, ValidationResult}
+};
+use crate::{lexer::Lexer, token::*};
+
+const KEYWORDS: [&str; 22] = [
+    "if", "else", "while", "for", "break", "continue", "fn", "let", "return", "or", "and", "in",
+    "class", "this", "super", "true", "false", "none", "try", "catch", "finally", "throw"
+];
+
+const KEYWORD: &str = "\x1b[35m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const OPERATOR: &str = "\x1b[36m";
+const BRACKET: &str = "\x1b[1;34m";
+const COMMENT: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+// rustyline helper for the REPL: highlighting, bracket matching, completion and
+// multi-line validation, all driven by the neptune lexer.
+pub struct ReplHelper {
+    pub names: Vec<String> // names bound in the session, refreshed after every input
+}
+
+impl Default for ReplHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplHelper {
+    pub fn new() -> Self {
+        Self { names: vec![] }
+    }
+}
+
+// byte ranges of every token in `line`, paired with the token itself.
+fn spans(line: &str) -> Option<Vec<(usize, usize, Token)>> {
+    let source = line.to_string();
+    let tokens = Lexer::new(&source).tokenize().ok()?;
+    Some(tokens.into_iter()
+        .filter(|token| token._type != TokenType::Eof)
+        .map(|token| (token.start, token.end, token))
+        .collect())
+}
+
+fn colour(_type: &TokenType) -> Option<&'static str> {
+    match _type {
+        TokenType::If | TokenType::Else | TokenType::While | TokenType::For | TokenType::Break | TokenType::Continue |
+        TokenType::FuncDeclare | TokenType::Declare | TokenType::Return | TokenType::Class | TokenType::This | TokenType::Super |
+        TokenType::Or | TokenType::And | TokenType::In | TokenType::Bool(_) | TokenType::None |
+        TokenType::Try | TokenType::Catch | TokenType::Finally | TokenType::Throw => Some(KEYWORD),
+        TokenType::String(_) | TokenType::Interpolation(_) => Some(STRING),
+        TokenType::Number(_) => Some(NUMBER),
+        TokenType::DocComment(_) => Some(COMMENT),
+        TokenType::Name(_) | TokenType::Eof | TokenType::Separate | TokenType::Comma | TokenType::Colon | TokenType::Dot |
+        TokenType::ParOpen | TokenType::ParClose | TokenType::BrackOpen | TokenType::BrackClose |
+        TokenType::SquareOpen | TokenType::SquareClose => None,
+        _ => Some(OPERATOR)
+    }
+}
+
+fn is_open(_type: &TokenType) -> bool {
+    matches!(_type, TokenType::ParOpen | TokenType::BrackOpen | TokenType::SquareOpen)
+}
+
+fn is_close(_type: &TokenType) -> bool {
+    matches!(_type, TokenType::ParClose | TokenType::BrackClose | TokenType::SquareClose)
+}
+
+// indices (into `spans`) of the bracket under the cursor and the one that matches it.
+fn matching_brackets(spans: &[(usize, usize, Token)], pos: usize) -> Option<(usize, usize)> {
+    let at = spans.iter().position(|(start, _, t)| *start == pos && (is_open(&t._type) || is_close(&t._type)))
+        .or_else(|| spans.iter().position(|(start, _, t)| *start + 1 == pos && (is_open(&t._type) || is_close(&t._type))))?;
+
+    let mut depth = 0;
+    if is_open(&spans[at].2._type) {
+        for (i, (_, _, t)) in spans.iter().enumerate().skip(at) {
+            if is_open(&t._type) { depth += 1 }
+            if is_close(&t._type) { depth -= 1 }
+            if depth == 0 { return Some((at, i)) }
+        }
+    } else {
+        for (i, (_, _, t)) in spans.iter().enumerate().take(at + 1).rev() {
+            if is_close(&t._type) { depth += 1 }
+            if is_open(&t._type) { depth -= 1 }
+            if depth == 0 { return Some((at, i)) }
+        }
+    }
+    None
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if line.starts_with(':') {
+            return Cow::Borrowed(line)
+        }
+        let spans = match spans(line) {
+            Some(spans) => spans,
+            None => return Cow::Borrowed(line) // doesn't lex yet (e.g. an unfinished string)
+        };
+        let brackets = matching_brackets(&spans, pos);
+
+        let mut highlighted = String::with_capacity(line.len() * 2);
+        let mut last = 0;
+        for (i, (start, end, token)) in spans.iter().enumerate() {
+            highlighted.push_str(&line[last..*start]);
+            let colour = match brackets {
+                Some((a, b)) if i == a || i == b => Some(BRACKET),
+                _ => colour(&token._type)
+            };
+            match colour {
+                Some(c) => {
+                    highlighted.push_str(c);
+                    highlighted.push_str(&line[*start..*end]);
+                    highlighted.push_str(RESET);
+                },
+                None => highlighted.push_str(&line[*start..*end])
+            }
+            last = *end;
+        }
+        highlighted.push_str(&line[last..]);
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true // the bracket under the cursor can change on any movement
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Result<(usize, Vec<String>)> {
+        let start = line[..pos].char_indices().rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let word = &line[start..pos];
+        if word.is_empty() {
+            return Ok((pos, vec![]))
+        }
+
+        let mut candidates: Vec<String> = KEYWORDS.iter()
+            .map(|k| k.to_string())
+            .chain(self.names.iter().cloned())
+            .filter(|name| name.starts_with(word))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult> {
+        if Lexer::is_complete(&ctx.input().to_string()) {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
@@ -1,83 +1,181 @@
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::cell::RefCell;
 use crate::{
     ast::*,
     token::*,
-    error::Error,
-    function::Function
+    error::{Error, ErrorKind},
+    function::{Function, Call, TailCall, arrange, was},
+    class::{Class, Instance},
+    iter::{Iter, Range},
+    map::{Map, Key},
+    diagnostic::similar,
+    natives,
+    ops,
+    heap
 };
 
 #[derive(Clone)]
 pub enum Object { // wrapper for multiple data types
     Number(f64),
     Bool(bool),
-    String(String),
+    String(Rc<str>), // strings never change, so copies can share one
     None,
-    Function(Function)
+    Function(Function),
+    List(Rc<RefCell<Vec<Object>>>), // shared, so every copy of a list sees the same mutations
+    Map(Rc<RefCell<Map>>),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+    Range(Range)
 }
 
+impl Object {
+    // structural equality for values, identity for functions
+    pub fn equals(&self, other: &Object) -> bool {
+        self.equal(other, &mut vec![])
+    }
+
+    // `comparing` holds the pairs of lists and maps already being compared further up. coming
+    // back around to one means both sides loop back the same way, so they're equal that far
+    fn equal(&self, other: &Object, comparing: &mut Vec<(usize, usize)>) -> bool {
+        match (self, other) {
+            (Object::Number(a), Object::Number(b)) => a == b,
+            (Object::Bool(a), Object::Bool(b)) => a == b,
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::None, Object::None) => true,
+            (Object::List(a), Object::List(b)) => {
+                Rc::ptr_eq(a, b) || nested(comparing, (address(a), address(b)), |comparing| {
+                    let (a, b) = (a.borrow(), b.borrow());
+                    a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.equal(y, comparing))
+                })
+            },
+            (Object::Map(a), Object::Map(b)) => {
+                Rc::ptr_eq(a, b) || nested(comparing, (address(a), address(b)), |comparing| {
+                    let (a, b) = (a.borrow(), b.borrow());
+                    a.len() == b.len() && a.entries().iter().all(|(k, v)| {
+                        Key::new(k).and_then(|k| b.get(&k)).is_some_and(|other| v.equal(other, comparing))
+                    })
+                })
+            },
+            (Object::Function(a), Object::Function(b)) => a.is(b),
+            (Object::Range(a), Object::Range(b)) => a == b,
+            (Object::Class(a), Object::Class(b)) => Rc::ptr_eq(a, b),
+            (Object::Instance(a), Object::Instance(b)) => Rc::ptr_eq(a, b),
+            _ => false
+        }
+    }
+}
+
+fn address<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as usize
+}
+
+// runs `compare` with `pair` marked as being compared, unless it already is
+fn nested(comparing: &mut Vec<(usize, usize)>, pair: (usize, usize), compare: impl FnOnce(&mut Vec<(usize, usize)>) -> bool) -> bool {
+    if comparing.contains(&pair) {
+        return true
+    }
+    comparing.push(pair);
+    let equal = compare(comparing);
+    comparing.pop();
+    equal
+}
+
+thread_local! {
+    // the lists and maps being printed right now, further up
+    static PRINTING: RefCell<Vec<usize>> = const { RefCell::new(vec![]) };
+}
+
+// prints a list or map with `print`, or with `looped` if it's inside itself and has come back around
+fn print_once(address: usize, looped: &str, f: &mut std::fmt::Formatter<'_>, print: impl FnOnce(&mut std::fmt::Formatter<'_>) -> std::fmt::Result) -> std::fmt::Result {
+    if PRINTING.with(|printing| printing.borrow().contains(&address)) {
+        return write!(f, "{}", looped)
+    }
+    PRINTING.with(|printing| printing.borrow_mut().push(address));
+    let result = print(f);
+    PRINTING.with(|printing| printing.borrow_mut().pop());
+    result
+}
+
+// a scope's locals, in the slots the resolver numbered them with
+pub type Environment = Rc<RefCell<Vec<Object>>>;
+
 pub struct Interpreter {
-    pub environments: Vec<HashMap<String, Object>>,
-    pub depths: HashMap<usize, usize>,
-    globals: HashMap<String, Object>
+    pub environments: Vec<Environment>,
+    pub locals: HashMap<usize, (usize, usize)>, // how many scopes out each local is, and its slot there
+    pub in_scope: HashMap<usize, Vec<String>>, // the local names around variables that aren't locals
+    pub tail_calls: HashSet<usize>, // calls that are the last thing their function does
+    pub globals: HashMap<String, Object>,
+    error_class: Rc<Class> // what caught runtime errors are instances of
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         let mut globals: HashMap<String, Object> = HashMap::new();
-        globals.insert("print".to_string(), Object::Function(Function::Native {
-            arg_len: 1,
-            body: Box::new(|args| {
-                println!("{}", args[0]);
-                Ok(Object::None)
-            }),
-            name: "print".to_string()
-        }));
-        globals.insert("time".to_string(), Object::Function(Function::Native {
-            arg_len: 0,
-            body: Box::new(|_args| {
-                Ok(Object::Number(
-                    SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("failure!").as_secs_f64() as f64
-                ))
-            }),
-            name: "time".to_string()
-        }));
-        Self { environments: vec![], depths: HashMap::new(), globals }
+        natives::define(&mut globals);
+        let error_class = natives::error_class();
+        globals.insert("Error".to_string(), Object::Class(error_class.clone()));
+        Self { environments: vec![], locals: HashMap::new(), in_scope: HashMap::new(), tail_calls: HashSet::new(), globals, error_class }
+    }
+
+    // every global name. locals only have slots by the time the program runs
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.globals.keys().cloned().collect();
+        names.sort();
+        names.dedup();
+        names
     }
 
     pub fn is_truthy(&self, obj: &Object) -> bool {
-        match obj {
-            &Object::Bool(v) => v,
-            &Object::None => false,
-            _ => true
-          }
+        ops::is_truthy(obj)
     }
 
     fn lookup(&mut self, name: &Token, id: &usize) -> Result<Object, Error> {
-        let distance = self.depths.get(id);
-        // println!("{:?} ({}) is at a depth of {:?}", name, id, distance);
-        if let None = distance {
+        let local = self.locals.get(id);
+        if let None = local {
             let obj = self.globals.get(&name.value);
             if let None = obj {
-                return Err(Error::Runtime(format!("Unkown variable '{}' [{}:{}]", name.value, name.line, name.column)))
+                return Err(self.unknown(name, id))
             } else {
                 return Ok(obj.unwrap().clone());
             }
         }
 
-        for (i, enviro) in self.environments.iter().rev().enumerate() {
-            if i == distance.unwrap().clone() {
-                let obj = enviro.get(&name.value);
-                return Ok(obj.unwrap().clone())
-            }
+        let (distance, slot) = *local.unwrap();
+        if let Some(obj) = self.lookup_at(distance, slot) {
+            return Ok(obj)
         }
 
-        Ok(Object::None)
+        Err(self.unknown(name, id))
     }
 
-    pub fn run(&mut self, nodes: &Vec<Node>) -> Result<Object, Error> {
+    fn unknown(&self, name: &Token, id: &usize) -> Error {
+        let mut error = Error::at(ErrorKind::Name, name, format!("Unknown variable '{}'.", name.value));
+        if let Error::Runtime(e) = &mut error {
+            let mut names = self.names();
+            names.extend(self.in_scope.get(id).into_iter().flatten().cloned());
+            e.help = similar(&name.value, names.iter()).map(|s| format!("did you mean `{}`?", s));
+        }
+        error
+    }
+
+    fn lookup_at(&self, distance: usize, slot: usize) -> Option<Object> {
+        let enviro = self.environment(distance)?;
+        let obj = enviro.borrow().get(slot).cloned();
+        obj
+    }
+
+    fn environment(&self, distance: usize) -> Option<&Environment> {
+        self.environments.get(self.environments.len().checked_sub(distance + 1)?)
+    }
+
+    pub fn run(&mut self, nodes: &[Node]) -> Result<Object, Error> {
         let f = self.traverse_block(nodes);
         match f {
             Err(Error::Runtime(v)) => Err(Error::Runtime(v)),
@@ -88,99 +186,265 @@ impl Interpreter {
 
     pub fn traverse(&mut self, node: &Node) -> Result<Object, Error> {
         match node {
-            Node::Block(nodes) => {
-                self.environments.push(HashMap::new());
-                // println!("{:?}", self.environments);
-                // println!("\x1b[32mMake (block).\x1b[0m");
-                // println!("after len: {}", self.environments.len());
-                // println!("Made block for: {:#?}", nodes);
-                Ok(self.traverse_block(nodes)?)
+            Node::Block { body: nodes, .. } => {
+                self.environments.push(Rc::new(RefCell::new(vec![])));
+                let result = self.traverse_block(nodes);
+                self.environments.pop();
+                result
             },
             Node::BinaryOperator {left: l, operator: o, right: r, ..} => Ok(self.binary_operator(l, o, r)?),
             Node::UnaryOperator { operator: o, child: c, .. } => Ok(self.unary(o, c)?),
             Node::Logical {left: l, operator: o, right: r, ..} => Ok(self.logical(l, o, r)?),
             Node::Literal { value: lit, .. } => Ok(self.literal(lit)),
-            Node::Declare {name, value, .. } => Ok(self.declare(name, value)?),
-            Node::Assign {id, name, value} => Ok(self.assign(id, name, value)?),
-            Node::Variable { id, name } => Ok(self.variable(name, id)?),
+            Node::Declare { id, name, value, .. } => Ok(self.declare(id, name, value)?),
+            Node::Assign { id, name, value, .. } => Ok(self.assign(id, name, value)?),
+            Node::Variable { id, name, .. } => Ok(self.variable(name, id)?),
             Node::If { condition, body, else_block, ..} => Ok(self.if_block(condition, body, else_block)?),
-            Node::While { condition, body, .. } => Ok(self.while_block(&condition, &body)?),
-            Node::DeclareFn { name, args, body, .. } => Ok(self.declare_fn(name, args, body)?),
+            Node::While { condition, body, increment, .. } => Ok(self.while_block(&condition, &body, increment)?),
+            Node::ForIn { variable, iterable, body, .. } => Ok(self.for_in(variable, iterable, body)?),
+            Node::Range { start, end, step, inclusive, operator, .. } => Ok(self.range(start, end, step, *inclusive, operator)?),
+            Node::Break { .. } => Err(Error::Break),
+            Node::Continue { .. } => Err(Error::Continue),
+            Node::DeclareFn { id, name, args, body, .. } => Ok(self.declare_fn(id, name, args, body)?),
+            Node::Lambda { keyword, args, body, .. } => Ok(Object::Function(self.function(keyword, args, body, false))),
             Node::Return { value, .. } => Ok(self.return_statement(value)?),
-            Node::FnCall { name, args, .. } => Ok(self.call(name, args)?),
-            // _ => todo!()
+            Node::Throw { keyword, value, .. } => self.throw(keyword, value),
+            Node::Try { body, catch, finally, .. } => self.try_block(body, catch, finally),
+            Node::FnCall { name, args, kwargs, paren, .. } => Ok(self.call(name, args, kwargs, paren, false)?),
+            Node::Interpolation { parts, .. } => Ok(self.interpolation(parts)?),
+            Node::Class { id, name, superclass, methods, .. } => Ok(self.declare_class(id, name, superclass, methods)?),
+            Node::Get { object, name, .. } => Ok(self.get(object, name)?),
+            Node::Set { object, name, value, .. } => Ok(self.set(object, name, value)?),
+            Node::This { id, keyword, .. } => Ok(self.lookup(keyword, id)?),
+            Node::Super { id, keyword, method, .. } => Ok(self.super_method(id, keyword, method)?),
+            Node::List { items, .. } => Ok(self.list(items)?),
+            Node::Map { entries, brace, .. } => Ok(self.map(entries, brace)?),
+            Node::Index { object, index, bracket, .. } => Ok(self.index(object, index, bracket)?),
+            Node::Slice { object, start, end, bracket, .. } => Ok(self.slice(object, start, end, bracket)?),
+            Node::SetIndex { object, index, value, bracket, .. } => Ok(self.set_index(object, index, value, bracket)?),
         }
     }
 
-    pub fn traverse_block(&mut self, stmts: &Vec<Node>) -> Result<Object, Error> {
-        for stmts in stmts {
-            match self.traverse(stmts) {
-                Err(Error::Runtime(v)) => return Err(Error::Runtime(v)),
-                Err(Error::Return(v)) => {
-                    // println!("\x1b[31mPurge (block).\x1b[0m");
-                    self.environments.pop();
-                    // println!("after len: {}", self.environments.len());
-                    return Err(Error::Return(v))
-                },
-                _ => continue
-            }
+    pub fn traverse_block(&mut self, stmts: &[Node]) -> Result<Object, Error> {
+        for stmt in stmts {
+            self.traverse(stmt)?;
         }
 
-        // println!("\x1b[31mPurge (block).\x1b[0m");
-        self.environments.pop();
-        // println!("after len: {}", self.environments.len());
         Ok(Object::None)
     }
 
-    fn call(&mut self, name: &Box<Node>, given_args: &Vec<Node>) -> Result<Object, Error> {
-        let func = self.traverse(&**name)?;
-        match func {
-            Object::Function(mut f) => {
-                match f {
-                    Function::UserDefined { ref args, ..} => {
-                        let mut evaled_args = vec![];
-                
-                        if given_args.len() != args.len() {
-                            return Err(Error::Runtime(format!("Expected {} arguments, found {}.", args.len(), given_args.len())))
-                        }
-        
-                        for arg in given_args {
-                            evaled_args.push(self.traverse(arg)?)
-                        }
-        
-                        Ok(f.call(self, evaled_args)?)
+    // `tail` is for calls that are returned straight away, which can be left to whoever called
+    // the function returning them
+    fn call(&mut self, name: &Box<Node>, given_args: &Vec<Node>, kwargs: &Vec<(Token, Node)>, paren: &Token, tail: bool) -> Result<Object, Error> {
+        let callee = self.traverse(&**name)?;
+        let mut f = match callee {
+            Object::Function(f) => f,
+            Object::Class(class) => { // calling a class makes an instance and runs its initializer
+                let instance = heap::instance(Instance::new(class.clone()));
+                match class.find_method("init") {
+                    Some(init) => init.bind(instance),
+                    None if !given_args.is_empty() || !kwargs.is_empty() => {
+                        let given = given_args.len() + kwargs.len();
+                        return Err(Error::at(ErrorKind::Argument, paren, format!("{}() takes no arguments, but {} {} given.", class.name.value, given, was(given))))
                     },
-                    Function::Native { arg_len, .. } => {
-                        let mut evaled_args = vec![];
-
-                        if given_args.len() != arg_len {
-                            return Err(Error::Runtime(format!("Expected {} arguments, found {}.", arg_len, given_args.len())))
-                        }
+                    None => return Ok(instance)
+                }
+            },
+            _ => return Err(Error::at(ErrorKind::Type, paren, format!("Can only call functions and classes, not {}.", callee)))
+        };
 
-                        for arg in given_args {
-                            evaled_args.push(self.traverse(arg)?)
-                        }
+        let mut evaled_args = vec![];
+        for arg in given_args {
+            evaled_args.push(self.traverse(arg)?)
+        }
+        let mut evaled_kwargs = vec![];
+        for (name, value) in kwargs {
+            evaled_kwargs.push((name.clone(), self.traverse(value)?));
+        }
+        let result = match &f {
+            // the arguments are checked while this function's still running, like in the vm
+            Function::UserDefined { args, is_initializer: false, .. } if tail => {
+                arrange(f.name(), args, evaled_args, evaled_kwargs).and_then(|values| {
+                    Err(Error::TailCall(Box::new(TailCall { f: f.clone(), values, paren: paren.clone() })))
+                })
+            },
+            _ => f.call(self, evaled_args, evaled_kwargs)
+        };
+        match result {
+            Err(Error::Runtime(mut e)) => {
+                e.unwind(f.name(), paren);
+                Err(Error::Runtime(e))
+            },
+            result => result
+        }
+    }
 
-                        Ok(f.call(self, evaled_args)?)
-                    }
+    fn declare_class(&mut self, id: &usize, name: &Token, superclass: &Option<Box<Node>>, methods: &Vec<Node>) -> Result<Object, Error> {
+        let mut parent = None;
+        if let Some(s) = superclass {
+            match self.traverse(s)? {
+                Object::Class(c) => parent = Some(c),
+                other => {
+                    let token = if let Node::Variable { name, .. } = &**s { name } else { name };
+                    return Err(Error::at(ErrorKind::Type, token, format!("Superclass must be a class, not {}.", other)))
                 }
             }
-            _ => return Err(Error::Runtime(format!("Can only call functions, not {}", func)))
+
+            // the methods close over a scope holding `super`, mirroring the resolver
+            self.environments.push(Rc::new(RefCell::new(vec![Object::Class(parent.clone().unwrap())])));
         }
+
+        let mut class = Class { name: name.clone(), superclass: parent.clone(), methods: HashMap::new() };
+        for method in methods {
+            if let Node::DeclareFn { name, args, body, .. } = method {
+                class.methods.insert(name.value.clone(), self.function(name, args, body, name.value == "init"));
+            }
+        }
+
+        if parent.is_some() {
+            self.environments.pop();
+        }
+        self.define(*id, &name.value, Object::Class(heap::class(class)));
+        Ok(Object::None)
     }
 
-    fn declare_fn(&mut self, name: &Token, args: &Vec<Token>, body: &Box<Node>) -> Result<Object, Error> {
-        let function = Function::UserDefined { args: args.clone(), body: *body.clone(), name: name.clone() };
-        if self.environments.len() == 0 {
-            self.globals.insert(name.value.clone(), Object::Function(function));
-        } else {
-            self.environments.last_mut().unwrap().insert(name.value.clone(), Object::Function(function));
+    // `super` is resolved to the scope around the method's `this` scope, so `this` is one closer
+    fn super_method(&mut self, id: &usize, keyword: &Token, method: &Token) -> Result<Object, Error> {
+        let (distance, slot) = *self.locals.get(id).unwrap();
+        let superclass = self.lookup_at(distance, slot);
+        let this = self.lookup_at(distance - 1, 0);
+
+        match (superclass, this) {
+            (Some(Object::Class(superclass)), Some(this)) => ops::get_super(&superclass, this, method),
+            _ => Err(Error::at(ErrorKind::Name, keyword, "Cannot use 'super' here.".to_string()))
         }
+    }
+
+    fn get(&mut self, object: &Box<Node>, name: &Token) -> Result<Object, Error> {
+        let o = self.traverse(object)?;
+        ops::get(o, name)
+    }
+
+    fn set(&mut self, object: &Box<Node>, name: &Token, value: &Box<Node>) -> Result<Object, Error> {
+        let o = self.traverse(object)?;
+        let v = self.traverse(value)?;
+        ops::set(o, name, v)?;
         Ok(Object::None)
     }
 
-    fn return_statement(&mut self, value: &Box<Node>) -> Result<Object, Error> {
+    fn interpolation(&mut self, parts: &Vec<Node>) -> Result<Object, Error> {
+        let mut string = String::new();
+        for part in parts {
+            string.push_str(&self.traverse(part)?.to_string());
+        }
+        Ok(Object::String(string.into()))
+    }
+
+    fn list(&mut self, items: &Vec<Node>) -> Result<Object, Error> {
+        let mut list = vec![];
+        for item in items {
+            list.push(self.traverse(item)?);
+        }
+        Ok(heap::list(list))
+    }
+
+    fn map(&mut self, entries: &Vec<(Node, Node)>, brace: &Token) -> Result<Object, Error> {
+        let mut map = Map::new();
+        for (key, value) in entries {
+            let k = self.traverse(key)?;
+            let v = self.traverse(value)?;
+            map.insert(ops::key(&k, brace)?, k, v);
+        }
+        Ok(heap::map(map))
+    }
+
+    fn index(&mut self, object: &Box<Node>, index: &Box<Node>, bracket: &Token) -> Result<Object, Error> {
+        let o = self.traverse(object)?;
+        let i = self.traverse(index)?;
+        ops::index(o, i, bracket)
+    }
+
+    fn slice(&mut self, object: &Box<Node>, start: &Option<Box<Node>>, end: &Option<Box<Node>>, bracket: &Token) -> Result<Object, Error> {
+        let o = self.traverse(object)?;
+        let start = match start {
+            Some(s) => Some(self.traverse(s)?),
+            None => None
+        };
+        let end = match end {
+            Some(e) => Some(self.traverse(e)?),
+            None => None
+        };
+        ops::slice(o, start, end, bracket)
+    }
+
+    fn set_index(&mut self, object: &Box<Node>, index: &Box<Node>, value: &Box<Node>, bracket: &Token) -> Result<Object, Error> {
+        let o = self.traverse(object)?;
+        let i = self.traverse(index)?;
         let v = self.traverse(value)?;
+        ops::set_index(o, i, v, bracket)?;
+        Ok(Object::None)
+    }
+
+    // a function closing over the scopes it's declared in, which makes them part of the heap
+    fn function(&self, name: &Token, args: &Rc<Vec<Param>>, body: &Rc<Node>, is_initializer: bool) -> Function {
+        heap::capture(&self.environments);
+        Function::UserDefined {
+            args: args.clone(),
+            body: body.clone(),
+            name: name.clone(),
+            closure: self.environments.clone(),
+            is_initializer,
+            bound: false
+        }
+    }
+
+    fn declare_fn(&mut self, id: &usize, name: &Token, args: &Rc<Vec<Param>>, body: &Rc<Node>) -> Result<Object, Error> {
+        let function = self.function(name, args, body, false);
+        self.define(*id, &name.value, Object::Function(function));
+        Ok(Object::None)
+    }
+
+    // binds what the declaration `id` declares in the innermost scope, or globally at the top level
+    fn define(&mut self, id: usize, name: &str, value: Object) {
+        match (self.locals.get(&id), self.environments.last()) {
+            (Some(&(_, slot)), Some(enviro)) => store(enviro, slot, value),
+            _ => { self.globals.insert(name.to_string(), value); }
+        }
+    }
+
+    // `finally` always runs, even when the body or handler returns, breaks or fails.
+    // an error (or return) from `finally` itself replaces whatever came before.
+    fn try_block(&mut self, body: &Box<Node>, catch: &Option<(Token, Box<Node>)>, finally: &Option<Box<Node>>) -> Result<Object, Error> {
+        let mut result = self.traverse(body);
+        if let Some((_, handler)) = catch {
+            result = match result {
+                Err(Error::Runtime(e)) => {
+                    let error = ops::error_object(e, &self.error_class);
+                    self.environments.push(Rc::new(RefCell::new(vec![error])));
+                    let result = self.traverse(handler);
+                    self.environments.pop();
+                    result
+                },
+                result => result
+            };
+        }
+
+        if let Some(finally) = finally {
+            self.traverse(finally)?;
+        }
+        result
+    }
+
+    fn throw(&mut self, keyword: &Token, value: &Box<Node>) -> Result<Object, Error> {
+        let value = self.traverse(value)?;
+        Err(ops::throw(value, keyword, &self.error_class))
+    }
+
+    fn return_statement(&mut self, value: &Box<Node>) -> Result<Object, Error> {
+        let v = match &**value {
+            Node::FnCall { id, name, args, kwargs, paren, .. } if self.tail_calls.contains(id) => self.call(name, args, kwargs, paren, true)?,
+            value => self.traverse(value)?
+        };
         Err(Error::Return(v))
     }
     
@@ -188,42 +452,73 @@ impl Interpreter {
         self.lookup(&name, id)
     }
     
-    pub fn declare(&mut self, name: &Token, value: &Box<Node>) -> Result<Object, Error> {
+    pub fn declare(&mut self, id: &usize, name: &Token, value: &Box<Node>) -> Result<Object, Error> {
         let v = self.traverse(value)?;
-        if self.environments.len() != 0 {
-            self.environments.last_mut().unwrap().insert(name.value.clone(), v);
-        } else {
-            self.globals.insert(name.value.clone(), v);
-        }
+        self.define(*id, &name.value, v);
         Ok(Object::None)
     }
     
     fn assign(&mut self, id: &usize, name: &Token, value: &Box<Node>) -> Result<Object, Error> {
         let v = self.traverse(value)?;
-        let distance = self.depths.get(&id);
-        if let None = distance {
+        let local = self.locals.get(&id);
+        if let None = local {
             self.globals.insert(name.value.clone(), v);
         } else {
-            for (i, enviro) in self.environments.iter_mut().rev().enumerate() {
-                if i == distance.unwrap().clone() {
-                    enviro.insert(name.value.clone(), v);
-                    break
-                }
+            let (distance, slot) = *local.unwrap();
+            if let Some(enviro) = self.environment(distance) {
+                store(enviro, slot, v);
             }
         }
         Ok(Object::None)
     }
     
-    fn while_block(&mut self, condition: &Box<Node>, body: &Box<Node>) -> Result<Object, Error> {
+    fn while_block(&mut self, condition: &Box<Node>, body: &Box<Node>, increment: &Option<Box<Node>>) -> Result<Object, Error> {
         let mut c = self.traverse(condition)?;
         while self.is_truthy(&c) {
-            self.traverse(&**body)?;
+            match self.traverse(&**body) {
+                Err(Error::Break) => break,
+                Err(Error::Continue) | Ok(_) => {},
+                Err(e) => return Err(e)
+            }
+            if let Some(i) = increment {
+                self.traverse(i)?;
+            }
             c = self.traverse(condition)?;
         }
 
         Ok(Object::None)
     }
     
+    fn for_in(&mut self, variable: &Token, iterable: &Box<Node>, body: &Box<Node>) -> Result<Object, Error> {
+        let i = self.traverse(iterable)?;
+        let mut iter = Iter::new(self, i, variable)?;
+
+        while let Some(item) = iter.next(self, variable)? {
+            self.environments.push(Rc::new(RefCell::new(vec![item])));
+            let result = self.traverse(body);
+            self.environments.pop();
+
+            match result {
+                Err(Error::Break) => break,
+                Err(Error::Continue) | Ok(_) => {},
+                Err(e) => return Err(e)
+            }
+        }
+
+        Ok(Object::None)
+    }
+
+    fn range(&mut self, start: &Box<Node>, end: &Box<Node>, step: &Option<Box<Node>>, inclusive: bool, operator: &Token) -> Result<Object, Error> {
+        let s = self.traverse(start)?;
+        let e = self.traverse(end)?;
+        let st = match step {
+            Some(st) => self.traverse(st)?,
+            None => Object::Number(1.0)
+        };
+
+        ops::range(operator, s, e, st, inclusive)
+    }
+
     fn if_block(&mut self, condition: &Box<Node>, body: &Box<Node>, else_block: &Option<Box<Node>>) -> Result<Object, Error> {
         let c = self.traverse(condition)?;
         if self.is_truthy(&c) {
@@ -239,112 +534,92 @@ impl Interpreter {
     fn logical(&mut self, left: &Box<Node>, operator: &Token, right: &Box<Node>) -> Result<Object, Error> {
         let l = self.traverse(left)?;
         let r = self.traverse(right)?;
-        
-        if operator._type == TokenType::Or {
-            if self.is_truthy(&l) {
-                return Ok(l)
-            }
-        } else if !self.is_truthy(&l) {
-            return Ok(l)
-        }
-
-        Ok(r)
-
+        Ok(ops::logical(operator, l, r))
     }
 
     fn binary_operator(&mut self, left: &Box<Node>, operator: &Token, right: &Box<Node>) -> Result<Object, Error> {
         let l = self.traverse(left)?;
         let r = self.traverse(right)?;
-
-        return match operator._type {
-            TokenType::Plus => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Number(left_val + right_val)),
-                (Object::String(left_val), Object::String(right_val)) => Ok(Object::String(left_val + &right_val)),
-                _ => Err(Error::Runtime(format!("Left and right values must both be numbers or strings for additon. [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::Minus => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Number(left_val - right_val)),
-                _=> Err(Error::Runtime(format!("Left and right values must both be numbers for subtraction. [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::Multiply => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Number(left_val * right_val)),
-                (Object::String(left_val), Object::Number(right_val)) => Ok(Object::String(left_val.repeat(right_val as usize))),
-                _=> Err(Error::Runtime(format!("Left and right values must both be numbers for multiplication. [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::Divide => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Number(left_val / right_val)),
-                _=> Err(Error::Runtime(format!("Left and right values must both be numbers for division. [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::Greater => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val > right_val)),
-                _=> Err(Error::Runtime(format!("Left and right values must both be numbers for comparions. [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::Less => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val < right_val)),
-                _=> Err(Error::Runtime(format!("Left and right values must both be numbers for comparions. [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::GreraterEqual => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val >= right_val)),
-                _=> Err(Error::Runtime(format!("Left and right values must both be numbers for comparions. [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::LessEqual => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val <= right_val)),
-                _=> Err(Error::Runtime(format!("Left and right values must both be numbers for comparions. [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::Equal => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val == right_val)),
-                _=> Err(Error::Runtime(format!("Left and right values must both be numbers for comparions. [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::NotEqual => match (l, r) {
-                (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val != right_val)),
-                _=> Err(Error::Runtime(format!("Left and right values must both be numbers for comparions. [{}:{}]", operator.line, operator.column)))
-            },
-            _ => Err(Error::Runtime(format!("Operator not implemented: {:?}", operator)))
-        }
-
+        ops::binary(operator, l, r)
     }
 
     fn unary(&mut self, operator: &Token, c: &Box<Node>) -> Result<Object, Error> {
         let child = self.traverse(c)?;
-
-        return match operator._type {
-            TokenType::Minus => match child {
-                Object::Number(v) => Ok(Object::Number(-(v))),
-                _ => Err(Error::Runtime(format!("Value must be number when negating (-). [{}:{}]", operator.line, operator.column)))
-            },
-            TokenType::Not => {
-                let truthy = self.is_truthy(&child);
-                return Ok(Object::Bool(!truthy))
-            },
-            _ => return Err(Error::Runtime(format!("Unrecognized unary operator: {:?} [{}:{}]", operator, operator.line, operator.column)))
-        }
+        ops::unary(operator, child)
     }
 
     fn literal(&self, node: &Literal) -> Object {
         return match node {
             Literal::Number(v) => Object::Number(*v),
             Literal::Bool(v) => Object::Bool(*v),
-            Literal::String(v) => Object::String(v.clone().to_string()),
+            Literal::String(v) => Object::String(v.as_str().into()),
             Literal::None => Object::None
         }
     }
 }
 
+impl Call for Interpreter {
+    fn invoke(&mut self, f: &mut Function, args: Vec<Object>) -> Result<Object, Error> {
+        f.call(self, args, vec![])
+    }
+}
+
 impl std::fmt::Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Object::Bool(b) => write!(f, "{}", b),
             Object::Function(func) => write!(f, "{}", func),
+            Object::Range(range) => write!(f, "{}", range),
+            Object::Class(class) => write!(f, "{}", class),
+            Object::Instance(instance) => write!(f, "{}", instance.borrow()),
             Object::Number(n) => {
                 let number = n.to_string();
                 if number.ends_with(".0") {
-                    write!(f, "{}", number.strip_suffix(".0").unwrap().to_string())
+                    write!(f, "{}", number.strip_suffix(".0").unwrap())
                 } else {
                     write!(f, "{}", number)
                 }
             },
             Object::String(s) => write!(f, "{}", s),
-            Object::None => write!(f, "none")
+            Object::None => write!(f, "none"),
+            Object::List(list) => print_once(address(list), "[...]", f, |f| {
+                write!(f, "[")?;
+                for (i, item) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", repr(item))?;
+                }
+                write!(f, "]")
+            }),
+            Object::Map(map) => print_once(address(map), "{...}", f, |f| {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().entries().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", repr(key), repr(value))?;
+                }
+                write!(f, "}}")
+            })
         }
     }
+}
+
+// puts `value` in one of a scope's slots. a declaration that never ran (say, the body of an
+// `if` without braces) leaves a gap before the ones after it, so gaps get filled with none
+fn store(enviro: &Environment, slot: usize, value: Object) {
+    let mut enviro = enviro.borrow_mut();
+    if slot >= enviro.len() {
+        enviro.resize(slot + 1, Object::None);
+    }
+    enviro[slot] = value;
+}
+
+// how a value looks inside a collection: strings are quoted, so `["1"]` and `[1]` look different
+pub fn repr(obj: &Object) -> String {
+    match obj {
+        Object::String(s) => format!("{:?}", s),
+        _ => obj.to_string()
+    }
 }
\ No newline at end of file
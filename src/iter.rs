@@ -0,0 +1,128 @@
+use std::{fmt::Display, rc::Rc, cell::RefCell};
+
+use crate::{
+    interpreter::Object,
+    function::Call,
+    token::Token,
+    error::{Error, ErrorKind}
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub start: f64,
+    pub end: f64,
+    pub step: f64,
+    pub inclusive: bool // `..=` rather than `..`
+}
+
+impl Range {
+    // whether `n` is one of the values the range steps through
+    pub fn contains(&self, n: f64) -> bool {
+        let offset = (n - self.start) / self.step;
+        offset >= 0.0 && offset.fract() == 0.0 && self.in_bounds(n)
+    }
+
+    fn in_bounds(&self, n: f64) -> bool {
+        match (self.step > 0.0, self.inclusive) {
+            (true, true) => n <= self.end,
+            (true, false) => n < self.end,
+            (false, true) => n >= self.end,
+            (false, false) => n > self.end
+        }
+    }
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dots = if self.inclusive { "..=" } else { ".." };
+        write!(f, "{}{}{}", Object::Number(self.start), dots, Object::Number(self.end))?;
+        if self.step != 1.0 {
+            write!(f, " step {}", Object::Number(self.step))?;
+        }
+        Ok(())
+    }
+}
+
+// the protocol methods are called by the loop itself, so errors in them are traced to it
+fn traced(result: Result<Object, Error>, name: &str, token: &Token) -> Result<Object, Error> {
+    match result {
+        Err(Error::Runtime(mut e)) => {
+            e.unwind(name, token);
+            Err(Error::Runtime(e))
+        },
+        result => result
+    }
+}
+
+// The state of a `for ... in` loop over some value.
+//
+// Instances take part through a protocol: if the class has an `iter()` method, its result
+// is used as the iterator, otherwise the instance is its own iterator. Each step calls the
+// iterator's `next()` method, and iteration stops once it gives back `none`.
+pub enum Iter {
+    Range(Range, f64),
+    List(Rc<RefCell<Vec<Object>>>, usize), // indexes the live list, so pushes during the loop are seen
+    Chars(Vec<char>, usize),
+    Keys(Vec<Object>, usize), // a snapshot of a map's keys, in insertion order
+    Instance(Object)
+}
+
+impl Iter {
+    pub fn new(caller: &mut impl Call, iterable: Object, token: &Token) -> Result<Iter, Error> {
+        match iterable {
+            Object::Range(range) => Ok(Iter::Range(range, range.start)),
+            Object::List(list) => Ok(Iter::List(list, 0)),
+            Object::String(s) => Ok(Iter::Chars(s.chars().collect(), 0)),
+            Object::Map(map) => Ok(Iter::Keys(map.borrow().entries().iter().map(|(k, _)| k.clone()).collect(), 0)),
+            Object::Instance(ref instance) => {
+                let iter = instance.borrow().class.find_method("iter").map(|m| m.bind(iterable.clone()));
+                match iter {
+                    Some(mut iter) => Ok(Iter::Instance(traced(caller.invoke(&mut iter, vec![]), "iter", token)?)),
+                    None => Ok(Iter::Instance(iterable))
+                }
+            },
+            other => Err(Error::at(ErrorKind::Type, token, format!("Cannot iterate over {}.", other)))
+        }
+    }
+
+    pub fn next(&mut self, caller: &mut impl Call, token: &Token) -> Result<Option<Object>, Error> {
+        match self {
+            Iter::Range(range, current) => {
+                if !range.in_bounds(*current) {
+                    return Ok(None)
+                }
+                let n = *current;
+                *current += range.step;
+                Ok(Some(Object::Number(n)))
+            },
+            Iter::List(list, i) => {
+                let item = list.borrow().get(*i).cloned();
+                *i += 1;
+                Ok(item)
+            },
+            Iter::Chars(chars, i) => {
+                let chr = chars.get(*i).map(|c| Object::String(c.to_string().into()));
+                *i += 1;
+                Ok(chr)
+            },
+            Iter::Keys(keys, i) => {
+                let key = keys.get(*i).cloned();
+                *i += 1;
+                Ok(key)
+            },
+            Iter::Instance(iterator) => {
+                let next = match &*iterator {
+                    Object::Instance(instance) => instance.borrow().class.find_method("next").map(|m| m.bind(iterator.clone())),
+                    _ => None
+                };
+                match next {
+                    Some(mut next) => match traced(caller.invoke(&mut next, vec![]), "next", token)? {
+                        Object::None => Ok(None),
+                        item => Ok(Some(item))
+                    },
+                    None => Err(Error::at(ErrorKind::Attribute, token, format!("{} has no next() method to iterate with.", iterator)))
+                }
+            }
+        }
+    }
+}
@@ -1,26 +1,52 @@
 use crate::token::*;
 use crate::error::Error;
+use crate::diagnostic::Diagnostic;
 use line_col::LineColLookup;
 
 pub struct Lexer<'a> {
     content: Vec::<char>,
+    offsets: Vec::<usize>, // byte offset of each character, which is what `lc_lookup` expects
     index: usize,
     chr: Option<char>,
-    lc_lookup: LineColLookup<'a>
+    lc_lookup: LineColLookup<'a>,
+    origin: (usize, usize), // where the source starts in its file (not [1:1] for interpolated expressions)
+    base: usize // the byte offset of that start
 }
 
 impl <'a>Lexer<'a> {
     pub fn new(source: &'a String) -> Self {
+        Self::nested(source, (1, 1), 0)
+    }
+
+    fn nested(source: &'a String, origin: (usize, usize), base: usize) -> Self {
         let content: Vec::<char> = source.chars().collect();
+        let mut offsets: Vec::<usize> = source.char_indices().map(|(i, _)| i).collect();
+        offsets.push(source.len()); // so the end of the file has a position too
         let index = 0;
         let chr = if content.len() != 0 {
             content[index]
         } else {' '}; // if the file is empty (just in case)
         Self {
             content,
+            offsets,
             index,
             chr: Some(chr),
-            lc_lookup: LineColLookup::new(&source)
+            lc_lookup: LineColLookup::new(&source),
+            origin,
+            base
+        }
+    }
+
+    fn line_col(&self) -> (usize, usize) {
+        let index = self.index.min(self.content.len());
+        let (line, column) = self.lc_lookup.get(self.offsets[index]);
+        // `lc_lookup` counts the column in bytes, but people count characters
+        let line_start = self.offsets[index] - (column - 1);
+        let column = index - self.offsets.partition_point(|&offset| offset < line_start) + 1;
+        if line == 1 {
+            (self.origin.0, column + self.origin.1 - 1)
+        } else {
+            (line + self.origin.0 - 1, column)
         }
     }
 
@@ -65,11 +91,21 @@ impl <'a>Lexer<'a> {
             "else" => TokenType::Else,
             "while" => TokenType::While,
             "for" => TokenType::For,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
+            "try" => TokenType::Try,
+            "catch" => TokenType::Catch,
+            "finally" => TokenType::Finally,
+            "throw" => TokenType::Throw,
             "fn" => TokenType::FuncDeclare,
             "let" => TokenType::Declare,
             "return" => TokenType::Return,
+            "class" => TokenType::Class,
+            "this" => TokenType::This,
+            "super" => TokenType::Super,
             "or" => TokenType::Or,
             "and" => TokenType::And,
+            "in" => TokenType::In,
             "true" => TokenType::Bool(true),
             "false" => TokenType::Bool(false), 
             "none" => TokenType::None,          
@@ -78,122 +114,325 @@ impl <'a>Lexer<'a> {
 
         self.index -= 1;
 
-        Token {
-            _type,
-            value: word,
-            line,
-            column
-        }
+        Token::new(_type, word, line, column)
     }
 
     fn get_number(&mut self, line: usize, column: usize) -> Result<Token, Error> {
         let mut number = String::new();
         while self.chr != None && (
             self.unwrap().is_numeric() ||
-            self.unwrap() == '.'
+            (self.unwrap() == '.' && self.peek().is_some_and(|c| c.is_numeric())) // not `0..10` or `1.method`
         ) {
             number.push(self.unwrap());
             self.increment();
         }
 
         if number.matches(".").count() > 1 {
-            return Err(Error::Syntax(format!("Invalid float. [{}:{}]", line, column)))
+            return Err(Error::Syntax(Diagnostic::new("E0004", "Invalid float.".to_string(), line, column)))
         }
         self.index -= 1;
         self.chr = Some(self.content[self.index]);
-        Ok(Token {
-            _type: TokenType::Number(number.parse::<f64>().unwrap()),
-            value: number,
-            line,
-            column
-        })
+        Ok(Token::new(TokenType::Number(number.parse::<f64>().unwrap()), number, line, column))
     }
 
-    fn get_str(&mut self, line: usize, column: usize) -> Result<Token, Error> {
+    fn is_triple_quote(&self) -> bool {
+        self.chr == Some('"') && self.peek() == Some('"') && self.content.get(self.index + 2) == Some(&'"')
+    }
+
+    // starts on the opening quote and leaves the lexer on the closing one. raw strings
+    // (`r"..."`) don't process escapes or interpolation, and triple-quoted strings can
+    // span several lines.
+    fn get_str(&mut self, line: usize, column: usize, raw: bool) -> Result<Token, Error> {
+        let start = if raw { self.index - 1 } else { self.index };
+        let triple = self.is_triple_quote();
+        if triple {
+            self.increment();
+            self.increment();
+        }
+        self.increment();
+        if triple && self.chr == Some('\n') { // a newline right after the opening quotes isn't part of the string
+            self.increment();
+        }
+
         let mut string = String::new();
-        while self.chr != None && (
-            self.unwrap() != '"'
-        ) {
-            string.push(self.unwrap());
+        let mut parts = Vec::<StringPart>::new();
+        loop {
+            match self.chr {
+                None => return Err(Error::Syntax(Diagnostic::new("E0002", "Unterminated string.".to_string(), line, column))),
+                Some('"') if !triple => break,
+                Some('"') if self.is_triple_quote() => {
+                    self.increment();
+                    self.increment();
+                    break
+                },
+                Some('\\') if !raw => string.push(self.escape(line, column)?),
+                Some('{') if !raw => {
+                    parts.push(StringPart::Literal(std::mem::take(&mut string)));
+                    parts.push(StringPart::Expression(self.interpolated(line, column)?));
+                },
+                Some(chr) => string.push(chr)
+            }
             self.increment();
         }
-        if self.chr == None {
-            return Err(Error::Syntax(format!("Unterminated string. [{}:{}]", line, column)))
+
+        let value: String = self.content[start..=self.index].iter().collect();
+        if parts.is_empty() {
+            return Ok(Token::new(TokenType::String(string), value, line, column))
         }
-        Ok(Token { _type: TokenType::String(string.clone()), value: string, line, column})
+        parts.push(StringPart::Literal(string));
+        parts.retain(|part| part != &StringPart::Literal(String::new()));
+        Ok(Token::new(TokenType::Interpolation(parts), value, line, column))
+    }
+
+    // starts on the `{` of an embedded expression and leaves the lexer on its `}`.
+    // the expression is lexed on its own, but keeps its real position in the file.
+    fn interpolated(&mut self, line: usize, column: usize) -> Result<Vec<Token>, Error> {
+        self.increment();
+        let origin = self.line_col();
+        let start = self.index;
+        let mut depth = 0;
+        loop {
+            match self.chr {
+                None => return Err(Error::Syntax(Diagnostic::new("E0002", "Unterminated string.".to_string(), line, column))),
+                Some('}') if depth == 0 => break,
+                Some('}') => depth -= 1,
+                Some('{') => depth += 1,
+                Some('"') => { // skip over strings inside the expression so their quotes don't end ours
+                    self.increment();
+                    while self.chr != None && self.unwrap() != '"' {
+                        if self.unwrap() == '\\' {
+                            self.increment();
+                        }
+                        self.increment();
+                    }
+                    if self.chr == None {
+                        return Err(Error::Syntax(Diagnostic::new("E0002", "Unterminated string.".to_string(), line, column)))
+                    }
+                },
+                _ => {}
+            }
+            self.increment();
+        }
+
+        let source: String = self.content[start..self.index].iter().collect();
+        let tokens = Lexer::nested(&source, origin, self.base + self.offsets[start]).tokenize()?;
+        if tokens.iter().all(|t| matches!(t._type, TokenType::Eof | TokenType::DocComment(_))) {
+            return Err(Error::Syntax(Diagnostic::new("E0005", "Expected an expression inside '{}' in string.".to_string(), origin.0, origin.1)))
+        }
+        Ok(tokens)
+    }
+
+    // starts on the backslash and leaves the lexer on the last character of the escape.
+    fn escape(&mut self, line: usize, column: usize) -> Result<char, Error> {
+        let lc = self.line_col();
+        self.increment();
+        let escaped = match self.chr {
+            None => return Err(Error::Syntax(Diagnostic::new("E0002", "Unterminated string.".to_string(), line, column))),
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('{') => '{',
+            Some('}') => '}',
+            Some('u') => {
+                self.increment();
+                if self.chr != Some('{') {
+                    return Err(Error::Syntax(Diagnostic::new("E0003", "Expected '{' after '\\u' in unicode escape.".to_string(), lc.0, lc.1)))
+                }
+                self.increment();
+
+                let mut hex = String::new();
+                while self.chr != None && self.unwrap() != '}' && hex.len() <= 6 {
+                    hex.push(self.unwrap());
+                    self.increment();
+                }
+                if self.chr != Some('}') {
+                    return Err(Error::Syntax(Diagnostic::new("E0003", "Expected '}' to close unicode escape.".to_string(), lc.0, lc.1)))
+                }
+
+                let code = u32::from_str_radix(&hex, 16).ok().filter(|_| !hex.is_empty() && hex.len() <= 6);
+                match code.and_then(char::from_u32) {
+                    Some(chr) => chr,
+                    None => return Err(Error::Syntax(Diagnostic::new("E0003", format!("Invalid unicode escape '\\u{{{}}}'.", hex), lc.0, lc.1)))
+                }
+            },
+            Some(chr) => return Err(Error::Syntax(Diagnostic::new("E0003", format!("Unknown escape sequence '\\{}'.", chr), lc.0, lc.1)))
+        };
+        Ok(escaped)
+    }
+
+    // leaves the lexer on the last character of the comment. only `///` comments
+    // become tokens (`////` and beyond are ordinary comments, like in rust).
+    fn line_comment(&mut self, line: usize, column: usize) -> Option<Token> {
+        let mut comment = String::new();
+        while self.chr != None && self.unwrap() != '\n' {
+            comment.push(self.unwrap());
+            self.increment();
+        }
+        self.index -= 1;
+        self.chr = Some(self.content[self.index]);
+
+        if comment.starts_with("///") && !comment.starts_with("////") {
+            Some(Token::new(TokenType::DocComment(comment[3..].trim().to_string()), comment, line, column))
+        } else {
+            None
+        }
+    }
+
+    // block comments nest, so `/* /* */ */` is a single comment.
+    fn block_comment(&mut self, line: usize, column: usize) -> Result<(), Error> {
+        let mut depth = 0;
+        while self.chr != None {
+            if self.unwrap() == '/' && self.peek() == Some('*') {
+                depth += 1;
+                self.increment();
+            } else if self.unwrap() == '*' && self.peek() == Some('/') {
+                depth -= 1;
+                self.increment();
+                if depth == 0 {
+                    return Ok(())
+                }
+            }
+            self.increment();
+        }
+        Err(Error::Syntax(Diagnostic::new("E0002", "Unterminated block comment.".to_string(), line, column)))
+    }
+
+    // true once every parenthesis, bracket, square bracket, string and comment opened in `source` has been
+    // closed. source that doesn't lex for any other reason counts as complete so the error
+    // can be reported.
+    pub fn is_complete(source: &String) -> bool {
+        let tokens = match Lexer::new(source).tokenize() {
+            Ok(tokens) => tokens,
+            Err(Error::Syntax(d)) => return d.code != "E0002", // unterminated
+            Err(_) => return true
+        };
+
+        let mut depth = 0;
+        for token in tokens {
+            match token._type {
+                TokenType::ParOpen | TokenType::BrackOpen | TokenType::SquareOpen => depth += 1,
+                TokenType::ParClose | TokenType::BrackClose | TokenType::SquareClose => depth -= 1,
+                _ => {}
+            }
+        }
+        depth <= 0
     }
 
     pub fn tokenize(&mut self) -> Result<Vec<Token>, Error> {
         let mut tokens: Vec<Token> = Vec::<Token>::new();
         while self.chr != None {
             let chr = self.chr.unwrap();
-            let lc = self.lc_lookup.get(self.index);
+            let lc = self.line_col();
+            let (start, count) = (self.index, tokens.len());
             match chr { // get ready for a big boy match statement
                 chr if chr.is_whitespace() => {}, // skip to increment
     
+                'r' if self.peek() == Some('"') => {
+                    self.increment();
+                    tokens.push(self.get_str(lc.0, lc.1, true)?);
+                },
+
                 chr if chr.is_alphabetic() => tokens.push(self.get_word(lc.0, lc.1)),
 
                 chr if chr.is_numeric() => {
                     tokens.push(self.get_number(lc.0, lc.1)?);
                 },
 
-                '"' => {
-                    self.increment();
-                    tokens.push(self.get_str(lc.0, lc.1)?);
+                '"' => tokens.push(self.get_str(lc.0, lc.1, false)?),
+                '*' => tokens.push(Token::new(TokenType::Multiply, "*".to_string(), lc.0, lc.1)),
+                '/' => {
+                    if self.peek() == Some('/') {
+                        if let Some(doc) = self.line_comment(lc.0, lc.1) {
+                            tokens.push(doc);
+                        }
+                    } else if self.peek() == Some('*') {
+                        self.block_comment(lc.0, lc.1)?;
+                    } else {
+                        tokens.push(Token::new(TokenType::Divide, "/".to_string(), lc.0, lc.1));
+                    }
                 },
-                '*' => tokens.push(Token {_type: TokenType::Multiply, value: "*".to_string(), line: lc.0, column: lc.1 }),
-                '/' => tokens.push(Token {_type: TokenType::Divide, value: "/".to_string(), line: lc.0, column: lc.1 }),
-                '(' => tokens.push(Token {_type: TokenType::ParOpen, value: "(".to_string(), line: lc.0, column: lc.1 }),
-                ')' => tokens.push(Token {_type: TokenType::ParClose, value: ")".to_string(), line: lc.0, column: lc.1 }),
-                '{' => tokens.push(Token {_type: TokenType::BrackOpen, value: "{".to_string(), line: lc.0, column: lc.1 }),
-                '}' => tokens.push(Token {_type: TokenType::BrackClose, value: "}".to_string(), line: lc.0, column: lc.1 }),
-                '+' => tokens.push(Token {_type: TokenType::Plus, value: "+".to_string(), line: lc.0, column: lc.1 }),
-                '-' => tokens.push(Token {_type: TokenType::Minus, value: "-".to_string(), line: lc.0, column: lc.1 }),
-                ';' => tokens.push(Token {_type: TokenType::Separate, value: ";".to_string(), line: lc.0, column: lc.1 }),
-                ',' => tokens.push(Token {_type: TokenType::Comma, value: ",".to_string(), line: lc.0, column: lc.1 }),
+                '(' => tokens.push(Token::new(TokenType::ParOpen, "(".to_string(), lc.0, lc.1)),
+                ')' => tokens.push(Token::new(TokenType::ParClose, ")".to_string(), lc.0, lc.1)),
+                '{' => tokens.push(Token::new(TokenType::BrackOpen, "{".to_string(), lc.0, lc.1)),
+                '}' => tokens.push(Token::new(TokenType::BrackClose, "}".to_string(), lc.0, lc.1)),
+                '[' => tokens.push(Token::new(TokenType::SquareOpen, "[".to_string(), lc.0, lc.1)),
+                ']' => tokens.push(Token::new(TokenType::SquareClose, "]".to_string(), lc.0, lc.1)),
+                '.' => {
+                    if self.peek() == Some('.') {
+                        self.increment();
+                        if self.peek() == Some('.') {
+                            tokens.push(Token::new(TokenType::Ellipsis, "...".to_string(), lc.0, lc.1));
+                            self.increment();
+                        } else if self.is_peek_equal() {
+                            tokens.push(Token::new(TokenType::DotDotEqual, "..=".to_string(), lc.0, lc.1));
+                            self.increment();
+                        } else {
+                            tokens.push(Token::new(TokenType::DotDot, "..".to_string(), lc.0, lc.1));
+                        }
+                    } else {
+                        tokens.push(Token::new(TokenType::Dot, ".".to_string(), lc.0, lc.1));
+                    }
+                },
+                ':' => tokens.push(Token::new(TokenType::Colon, ":".to_string(), lc.0, lc.1)),
+                '+' => tokens.push(Token::new(TokenType::Plus, "+".to_string(), lc.0, lc.1)),
+                '-' => tokens.push(Token::new(TokenType::Minus, "-".to_string(), lc.0, lc.1)),
+                ';' => tokens.push(Token::new(TokenType::Separate, ";".to_string(), lc.0, lc.1)),
+                '|' => tokens.push(Token::new(TokenType::Pipe, "|".to_string(), lc.0, lc.1)),
+                ',' => tokens.push(Token::new(TokenType::Comma, ",".to_string(), lc.0, lc.1)),
                 '>' => {
                     if self.is_peek_equal() {
-                        tokens.push(Token {_type: TokenType::GreraterEqual, value: ">=".to_string(), line: lc.0, column: lc.1 });
+                        tokens.push(Token::new(TokenType::GreraterEqual, ">=".to_string(), lc.0, lc.1));
                         self.increment();
                     } else {
-                        tokens.push(Token {_type: TokenType::Greater, value: ">".to_string(), line: lc.0, column: lc.1 });
+                        tokens.push(Token::new(TokenType::Greater, ">".to_string(), lc.0, lc.1));
                     } 
                 },
 
                 '<' => {
                     if self.is_peek_equal() {
-                        tokens.push(Token {_type: TokenType::LessEqual, value: "<=".to_string(), line: lc.0, column: lc.1 });
+                        tokens.push(Token::new(TokenType::LessEqual, "<=".to_string(), lc.0, lc.1));
                         self.increment();
                     } else {
-                        tokens.push(Token {_type: TokenType::Less, value: "<".to_string(), line: lc.0, column: lc.1 });
+                        tokens.push(Token::new(TokenType::Less, "<".to_string(), lc.0, lc.1));
                     }
                 },
 
                 '=' => {
 
                     if self.is_peek_equal() {
-                        tokens.push(Token {_type: TokenType::Equal, value: "==".to_string(), line: lc.0, column: lc.1 });
+                        tokens.push(Token::new(TokenType::Equal, "==".to_string(), lc.0, lc.1));
                         self.increment();
                     } else {
-                        tokens.push(Token {_type: TokenType::Assign, value: "=".to_string(), line: lc.0, column: lc.1 });
+                        tokens.push(Token::new(TokenType::Assign, "=".to_string(), lc.0, lc.1));
                     }
                 },
 
                 '!' => {
                     if self.is_peek_equal() {
-                        tokens.push(Token {_type: TokenType::NotEqual, value: "!=".to_string(), line: lc.0, column: lc.1 });
+                        tokens.push(Token::new(TokenType::NotEqual, "!=".to_string(), lc.0, lc.1));
                         self.increment();
                     } else {
-                        tokens.push(Token {_type: TokenType::Not, value: "!".to_string(), line: lc.0, column: lc.1 });
+                        tokens.push(Token::new(TokenType::Not, "!".to_string(), lc.0, lc.1));
                     }
                 },
 
-                _ => return Err(Error::Syntax(format!("Unkown character '{}' [{}:{}]", chr, lc.0, lc.1)))
+                _ => return Err(Error::Syntax(Diagnostic::new("E0001", format!("Unknown character '{}'.", chr), lc.0, lc.1)))
+            }
+            if tokens.len() > count { // every case leaves the lexer on the last character of its token
+                let token = tokens.last_mut().unwrap();
+                token.start = self.base + self.offsets[start];
+                token.end = self.base + self.offsets[self.index + 1];
             }
             self.increment();
         }
-        let lc = self.lc_lookup.get(self.index);
-        tokens.push(Token {_type: TokenType::Eof, value: "<eof>".to_string(), line: lc.0, column: lc.1 });
+        let lc = self.line_col();
+        let mut eof = Token::new(TokenType::Eof, "<eof>".to_string(), lc.0, lc.1);
+        eof.start = self.base + self.offsets[self.content.len()];
+        eof.end = eof.start;
+        tokens.push(eof);
         Ok(tokens)
     }
 }
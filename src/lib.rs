@@ -5,4 +5,17 @@ pub mod interpreter;
 pub mod token;
 pub mod ast;
 pub mod error;
-pub mod function;
\ No newline at end of file
+pub mod diagnostic;
+pub mod function;
+pub mod class;
+pub mod natives;
+pub mod map;
+pub mod iter;
+pub mod session;
+pub mod helper;
+pub mod ops;
+pub mod chunk;
+pub mod compiler;
+pub mod vm;
+pub mod heap;
+pub mod optimizer;
\ No newline at end of file
@@ -2,13 +2,17 @@ extern crate neptune;
 use neptune::{
     lexer::Lexer,
     parser::Parser,
-    resolver::Resolver,
+    session::Session,
+    vm::Vm,
+    optimizer::{Optimizer, describe},
+    helper::ReplHelper,
     interpreter::*,
     error::Error
 };
 use std::{
     fs::{read_to_string},
     env::args,
+    time::Instant,
 };
 use rustyline::{
     Editor,
@@ -17,69 +21,183 @@ use rustyline::{
 };
 
 const VERSION: &str = "1.0.0";
+const HISTORY: &str = "npt-history.txt"; // kept in the working directory
 
-fn handle_errors(result: Result<Object, Error>) {
+// `source` is what was run, so the failing line can be quoted from it
+fn handle_errors(result: Result<Object, Error>, source: &str, path: &str) {
     match result {
-        Err(Error::Runtime(v)) => println!("\x1b[31mRuntime Error: {}\x1b[0m", v),
-        Err(Error::Syntax(v)) => println!("\x1b[31mSyntax error: {}\x1b[0m", v),
+        Err(Error::Runtime(e)) => println!("{}", e.render(source, path)),
+        Err(Error::Syntax(d)) => println!("{}", d.render(source, path)),
+        Err(Error::Parse(ds)) => {
+            for d in &ds {
+                println!("{}\n", d.render(source, path));
+            }
+            println!("\x1b[1;31merror\x1b[0m\x1b[1m: aborting due to {} syntax errors\x1b[0m", ds.len());
+        },
         _ => return
     }
 }
 
-fn run(file: String) -> Result<Object, Error> {
-    let tokens = Lexer::new(&file).tokenize()?;
-    let ast = Parser::new(&tokens).parse()?;
-    let mut interpreter = Interpreter::new();
-    Resolver::new(&mut interpreter).resolve_block(&ast)?;
-    interpreter.run(&ast)
+const HELP: &str = "Commands:
+  :ast <code>     print the syntax tree of <code>
+  :tokens <code>  print the tokens of <code>
+  :bytecode <code> print what the vm would run for <code>
+  :opt <code>     list what the optimizer changes in <code>
+  :env            list every name bound in the session
+  :reset          start over with a fresh session
+  :load <file>    run a file in the current session
+  :time <code>    run <code> and report how long it took
+  :help           show this message";
+
+fn command(session: &mut Session, line: &str) {
+    let (name, arg) = match line.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim().to_string()),
+        None => (line, String::new())
+    };
+
+    match name {
+        ":ast" => {
+            let result = Lexer::new(&arg).tokenize().and_then(|tokens| Parser::new(&tokens).parse());
+            match result {
+                Ok(ast) => for node in ast { println!("{:#?}", node) },
+                Err(e) => handle_errors(Err(e), &arg, "<repl>")
+            }
+        },
+        ":tokens" => match Lexer::new(&arg).tokenize() {
+            Ok(tokens) => for token in tokens {
+                println!("[{}:{}] {:?}", token.line, token.column, token._type)
+            },
+            Err(e) => handle_errors(Err(e), &arg, "<repl>")
+        },
+        ":bytecode" => match Vm::new().compile(&arg) {
+            Ok(script) => print!("{}", script),
+            Err(e) => handle_errors(Err(e), &arg, "<repl>")
+        },
+        ":opt" => dump_opt(&arg, "<repl>"),
+        ":env" => {
+            let mut names: Vec<&String> = session.interpreter.globals.keys().collect();
+            names.sort();
+            for name in names {
+                println!("{} = {}", name, session.interpreter.globals[name]);
+            }
+        },
+        ":reset" => {
+            let optimize = session.optimize;
+            *session = Session::new();
+            session.optimize = optimize;
+            session.interactive = true;
+            println!("Session reset.");
+        },
+        ":load" => match read_to_string(&arg) {
+            Ok(file) => handle_errors(session.run(&file), &file, &arg),
+            Err(e) => println!("\x1b[31mCouldn't read '{}': {}\x1b[0m", arg, e)
+        },
+        ":time" => {
+            let start = Instant::now();
+            let result = session.run(&arg);
+            let elapsed = start.elapsed();
+            echo(result, &arg);
+            println!("\x1b[32mTook {:?}\x1b[0m", elapsed);
+        },
+        ":help" => println!("{}", HELP),
+        _ => println!("\x1b[31mUnknown command '{}'. Type :help for a list of commands.\x1b[0m", name)
+    }
+}
+
+// what the optimizer would change in `source`, without running it
+fn dump_opt(source: &String, path: &str) {
+    let result = Lexer::new(source).tokenize().and_then(|tokens| Parser::new(&tokens).parse());
+    match result {
+        Ok(ast) => {
+            let mut optimizer = Optimizer::new();
+            optimizer.optimize(ast);
+            optimizer.changes.sort_by_key(|change| change.span.start);
+            if optimizer.changes.is_empty() {
+                println!("Nothing to optimize.");
+            }
+            for change in &optimizer.changes {
+                println!("{}", describe(change, source));
+            }
+        },
+        Err(e) => handle_errors(Err(e), source, path)
+    }
 }
 
-fn run_prompt() {
+fn echo(result: Result<Object, Error>, source: &str) {
+    match result {
+        Ok(Object::None) => {},
+        Ok(value) => println!("{}", value),
+        err => handle_errors(err, source, "<repl>")
+    }
+}
+
+fn run_prompt(optimize: bool) {
     println!("\x1b[32mShell version {}", VERSION);
-    println!("Supported operators: +, -, *, /, ()");
+    println!("Type \":help\" for a list of commands.");
     println!("Type \"exit\" or hit ^C to exit.\x1b[0m");
     
-    let mut editor = Editor::<()>::new().expect("Something went wrong with initializing the prompt.");
+    let mut editor = Editor::<ReplHelper>::new().expect("Something went wrong with initializing the prompt.");
+    editor.set_helper(Some(ReplHelper::new()));
+    let mut session = Session::new();
+    session.optimize = optimize;
+    session.interactive = true;
 
-    if editor.load_history("npt-hisory.txt").is_err() {
-        if std::fs::File::create("npt-history.txt").is_err() {
-            println!("An error occured with making a history file");
-            return
-        }
+    if editor.load_history(HISTORY).is_err() && std::fs::File::create(HISTORY).is_err() {
+        println!("An error occured with making a history file");
+        return
     }
 
     loop {
-        let line = editor.readline("> ");
+        let line = editor.readline("> "); // the helper's validator keeps reading until brackets balance
         match line {
             Ok(content) => {
-                editor.add_history_entry(content.as_str());
+                let content = content.trim();
+                editor.add_history_entry(content);
                 match content {
-                    content if content == "exit".to_string() => break,
+                    "exit" => break,
                     content if content.is_empty() => continue,
-                    content if (content.chars().collect::<Vec<char>>()[0]).is_whitespace() => continue,
-                    _ => handle_errors(run(content))
+                    content if content.starts_with(':') => command(&mut session, content),
+                    _ => echo(session.run(&content.to_string()), content)
                 }
+                editor.helper_mut().unwrap().names = session.interpreter.names();
             },
             Err(ReadlineError::Interrupted) => {
                 println!("^C");
                 break;
             },
+            Err(ReadlineError::Eof) => break,
             Err(e) => { println!("Unexpected error: {}", e); break }
         }
     }
 
-    editor.save_history("npt-history.txt").expect("Something went wrong while saving the history.");
+    editor.save_history(HISTORY).expect("Something went wrong while saving the history.");
 }
 
 fn main() {
     std::env::set_var("RUST_BACKTRACE", "1");
     let args: Vec<String> = args().collect();
-    if args.len() == 1 {
-        // return println!("CLI is not yet implemented. Please supply a file to run.")
-        run_prompt();
-    } else {
-        let file = read_to_string(args[1].as_str()).expect("Error reading the file");
-        handle_errors(run(file))
+    // `--vm` runs the file on the bytecode vm instead of the tree-walker, `--opt` runs it through
+    // the optimizer first, and `--dump-opt` lists what the optimizer would change instead of running it
+    let flag = |name: &str| args.iter().any(|a| a == name);
+    let (vm, optimize) = (flag("--vm"), flag("--opt"));
+    match args.iter().skip(1).find(|a| !a.starts_with("--")) {
+        None => run_prompt(optimize),
+        Some(path) => {
+            let file = read_to_string(path).expect("Error reading the file");
+            if flag("--dump-opt") {
+                return dump_opt(&file, path)
+            }
+            let result = if vm {
+                let mut vm = Vm::new();
+                vm.optimize = optimize;
+                vm.run(&file)
+            } else {
+                let mut session = Session::new();
+                session.optimize = optimize;
+                session.run(&file)
+            };
+            handle_errors(result, &file, path)
+        }
     }
 
 }
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use crate::interpreter::Object;
+
+// The hashable subset of `Object` that can be used as a map key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Number(u64), // the bits of the f64, with -0 folded into 0
+    Bool(bool),
+    String(String),
+    None
+}
+
+impl Key {
+    pub fn new(obj: &Object) -> Option<Key> {
+        match obj {
+            Object::Number(n) if *n == 0.0 => Some(Key::Number(0f64.to_bits())),
+            Object::Number(n) => Some(Key::Number(n.to_bits())),
+            Object::Bool(b) => Some(Key::Bool(*b)),
+            Object::String(s) => Some(Key::String(s.to_string())),
+            Object::None => Some(Key::None),
+            _ => None
+        }
+    }
+}
+
+// A map that remembers insertion order, so iterating over it is deterministic.
+#[derive(Clone)]
+pub struct Map {
+    entries: Vec<(Object, Object)>,
+    indices: HashMap<Key, usize>
+}
+
+impl Default for Map {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self { entries: vec![], indices: HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Object> {
+        self.indices.get(key).map(|i| &self.entries[*i].1)
+    }
+
+    pub fn contains(&self, key: &Key) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    // overwriting a key keeps its original position
+    pub fn insert(&mut self, key: Key, key_obj: Object, value: Object) {
+        match self.indices.get(&key) {
+            Some(i) => self.entries[*i].1 = value,
+            None => {
+                self.indices.insert(key, self.entries.len());
+                self.entries.push((key_obj, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Object> {
+        let i = self.indices.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for index in self.indices.values_mut() {
+            if *index > i {
+                *index -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn entries(&self) -> &Vec<(Object, Object)> {
+        &self.entries
+    }
+}
@@ -0,0 +1,181 @@
+use std::{collections::HashMap, rc::Rc};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::{
+    interpreter::{Object, repr},
+    function::Function,
+    class::Class,
+    map::{Map, Key},
+    error::{Error, ErrorKind},
+    heap::{self, list}
+};
+
+fn native(globals: &mut HashMap<String, Object>, name: &str, arg_len: usize, body: fn(&Vec<Object>) -> Result<Object, Error>) {
+    globals.insert(name.to_string(), Object::Function(Function::Native {
+        arg_len,
+        optional: 0,
+        body: Box::new(body),
+        name: name.to_string(),
+        this: None
+    }));
+}
+
+// turns a (possibly negative) index into a position in a list of `len` items.
+// `len` itself is allowed when `inclusive` is set, so `insert` can append.
+fn position(name: &str, index: &Object, len: usize, inclusive: bool) -> Result<usize, Error> {
+    let i = match index {
+        Object::Number(n) if n.fract() == 0.0 => *n,
+        _ => return Err(Error::new(ErrorKind::Type, format!("{}() expects a whole number index, not {}.", name, index)))
+    };
+    let limit = if inclusive { len + 1 } else { len };
+    let position = if i < 0.0 { len as f64 + i } else { i };
+    if position < 0.0 || position >= limit as f64 {
+        return Err(Error::new(ErrorKind::Index, format!("{}() index {} is out of range for a list of length {}.", name, i, len)))
+    }
+    Ok(position as usize)
+}
+
+// every native function, bound into the interpreter's globals
+pub fn define(globals: &mut HashMap<String, Object>) {
+    native(globals, "print", 1, |args| {
+        println!("{}", args[0]);
+        Ok(Object::None)
+    });
+
+    native(globals, "time", 0, |_args| {
+        Ok(Object::Number(
+            SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("failure!").as_secs_f64()
+        ))
+    });
+
+    native(globals, "len", 1, |args| {
+        match &args[0] {
+            Object::List(list) => Ok(Object::Number(list.borrow().len() as f64)),
+            Object::String(s) => Ok(Object::Number(s.chars().count() as f64)),
+            Object::Map(map) => Ok(Object::Number(map.borrow().len() as f64)),
+            other => Err(Error::new(ErrorKind::Type, format!("len() expects a list, map or string, not {}.", other)))
+        }
+    });
+
+    native(globals, "push", 2, |args| {
+        match &args[0] {
+            Object::List(list) => {
+                list.borrow_mut().push(args[1].clone());
+                Ok(Object::None)
+            },
+            other => Err(Error::new(ErrorKind::Type, format!("push() expects a list, not {}.", other)))
+        }
+    });
+
+    native(globals, "pop", 1, |args| {
+        match &args[0] {
+            Object::List(list) => match list.borrow_mut().pop() {
+                Some(item) => Ok(item),
+                None => Err(Error::new(ErrorKind::Value, "pop() can't pop from an empty list.".to_string()))
+            },
+            other => Err(Error::new(ErrorKind::Type, format!("pop() expects a list, not {}.", other)))
+        }
+    });
+
+    native(globals, "insert", 3, |args| {
+        match &args[0] {
+            Object::List(list) => {
+                let len = list.borrow().len();
+                let i = position("insert", &args[1], len, true)?;
+                list.borrow_mut().insert(i, args[2].clone());
+                Ok(Object::None)
+            },
+            other => Err(Error::new(ErrorKind::Type, format!("insert() expects a list, not {}.", other)))
+        }
+    });
+
+    native(globals, "remove", 2, |args| {
+        match &args[0] {
+            Object::List(list) => {
+                let len = list.borrow().len();
+                let i = position("remove", &args[1], len, false)?;
+                Ok(list.borrow_mut().remove(i))
+            },
+            Object::Map(map) => {
+                let removed = Key::new(&args[1]).and_then(|key| map.borrow_mut().remove(&key));
+                match removed {
+                    Some(value) => Ok(value),
+                    None => Err(Error::new(ErrorKind::Key, format!("remove() key {} isn't in the map.", repr(&args[1]))))
+                }
+            },
+            other => Err(Error::new(ErrorKind::Type, format!("remove() expects a list or map, not {}.", other)))
+        }
+    });
+
+    native(globals, "keys", 1, |args| {
+        match &args[0] {
+            Object::Map(map) => Ok(list(map.borrow().entries().iter().map(|(k, _)| k.clone()).collect())),
+            other => Err(Error::new(ErrorKind::Type, format!("keys() expects a map, not {}.", other)))
+        }
+    });
+
+    native(globals, "values", 1, |args| {
+        match &args[0] {
+            Object::Map(map) => Ok(list(map.borrow().entries().iter().map(|(_, v)| v.clone()).collect())),
+            other => Err(Error::new(ErrorKind::Type, format!("values() expects a map, not {}.", other)))
+        }
+    });
+
+    native(globals, "items", 1, |args| { // [[key, value], ...]
+        match &args[0] {
+            Object::Map(map) => Ok(list(map.borrow().entries().iter().map(|(k, v)| list(vec![k.clone(), v.clone()])).collect())),
+            other => Err(Error::new(ErrorKind::Type, format!("items() expects a map, not {}.", other)))
+        }
+    });
+
+    // collects now, giving back how many values it freed
+    native(globals, "gc", 0, |_args| {
+        Ok(Object::Number(heap::collect() as f64))
+    });
+
+    native(globals, "gc_stats", 0, |_args| {
+        let stats = heap::stats();
+        let mut map = Map::new();
+        for (name, value) in [
+            ("tracked", stats.tracked),
+            ("allocated", stats.allocated),
+            ("collections", stats.collections),
+            ("freed", stats.freed),
+            ("threshold", stats.threshold)
+        ] {
+            let key = Object::String(name.into());
+            map.insert(Key::new(&key).unwrap(), key, Object::Number(value as f64));
+        }
+        Ok(heap::map(map))
+    });
+
+    // how many values get made between collections, where 0 only collects when `gc()` is called
+    native(globals, "gc_threshold", 1, |args| {
+        match &args[0] {
+            Object::Number(n) if n.fract() == 0.0 && *n >= 0.0 => {
+                heap::set_threshold(*n as usize);
+                Ok(Object::None)
+            },
+            other => Err(Error::new(ErrorKind::Value, format!("gc_threshold() expects a whole number of at least 0, not {}.", other)))
+        }
+    });
+}
+
+// the class runtime errors are caught as, which scripts can throw too: `Error("message")`
+pub fn error_class() -> Rc<Class> {
+    let mut class = Class::builtin("Error");
+    class.methods.insert("init".to_string(), Function::Native {
+        arg_len: 1,
+        optional: 1,
+        body: Box::new(|args| { // `this`, then the message if there is one
+            if let (Object::Instance(instance), Some(message)) = (&args[0], args.get(1)) {
+                instance.borrow_mut().fields.insert("message".to_string(), message.clone());
+            }
+            Ok(args[0].clone())
+        }),
+        name: "init".to_string(),
+        this: None
+    });
+    Rc::new(class)
+}
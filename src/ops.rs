@@ -0,0 +1,277 @@
+use std::rc::Rc;
+use crate::{
+    interpreter::{Object, repr},
+    token::{Token, TokenType},
+    error::{Error, ErrorKind, RuntimeError},
+    class::{Class, Instance},
+    iter::Range,
+    map::Key,
+    heap
+};
+
+// What the operators do to values once their operands have been evaluated. Both the
+// tree-walker and the vm go through these, so the two can't disagree about the language.
+
+pub fn is_truthy(obj: &Object) -> bool {
+    match obj {
+        Object::Bool(v) => *v,
+        Object::None => false,
+        _ => true
+    }
+}
+
+pub fn binary(operator: &Token, l: Object, r: Object) -> Result<Object, Error> {
+    match operator._type {
+        TokenType::Plus => match (l, r) {
+            (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Number(left_val + right_val)),
+            (Object::String(left_val), Object::String(right_val)) => Ok(Object::String(format!("{}{}", left_val, right_val).into())),
+            (Object::List(left_val), Object::List(right_val)) => {
+                let mut list = left_val.borrow().clone();
+                list.extend(right_val.borrow().iter().cloned());
+                Ok(heap::list(list))
+            },
+            _ => Err(Error::at(ErrorKind::Type, operator, "Left and right values must both be numbers, strings or lists for additon.".to_string()))
+        },
+        TokenType::Minus => match (l, r) {
+            (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Number(left_val - right_val)),
+            _=> Err(Error::at(ErrorKind::Type, operator, "Left and right values must both be numbers for subtraction.".to_string()))
+        },
+        TokenType::Multiply => match (l, r) {
+            (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Number(left_val * right_val)),
+            (Object::String(left_val), Object::Number(right_val)) => repeat(&left_val, right_val, operator),
+            _=> Err(Error::at(ErrorKind::Type, operator, "Left and right values must both be numbers for multiplication.".to_string()))
+        },
+        TokenType::Divide => match (l, r) {
+            (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Number(left_val / right_val)),
+            _=> Err(Error::at(ErrorKind::Type, operator, "Left and right values must both be numbers for division.".to_string()))
+        },
+        TokenType::Greater => match (l, r) {
+            (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val > right_val)),
+            _=> Err(Error::at(ErrorKind::Type, operator, "Left and right values must both be numbers for comparions.".to_string()))
+        },
+        TokenType::Less => match (l, r) {
+            (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val < right_val)),
+            _=> Err(Error::at(ErrorKind::Type, operator, "Left and right values must both be numbers for comparions.".to_string()))
+        },
+        TokenType::GreraterEqual => match (l, r) {
+            (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val >= right_val)),
+            _=> Err(Error::at(ErrorKind::Type, operator, "Left and right values must both be numbers for comparions.".to_string()))
+        },
+        TokenType::LessEqual => match (l, r) {
+            (Object::Number(left_val), Object::Number(right_val)) => Ok(Object::Bool(left_val <= right_val)),
+            _=> Err(Error::at(ErrorKind::Type, operator, "Left and right values must both be numbers for comparions.".to_string()))
+        },
+        TokenType::Equal => Ok(Object::Bool(l.equals(&r))),
+        TokenType::NotEqual => Ok(Object::Bool(!l.equals(&r))),
+        TokenType::In => match (l, r) {
+            (item, Object::List(list)) => Ok(Object::Bool(list.borrow().iter().any(|i| i.equals(&item)))),
+            (Object::String(sub), Object::String(s)) => Ok(Object::Bool(s.contains(&*sub))),
+            (key, Object::Map(map)) => Ok(Object::Bool(Key::new(&key).is_some_and(|k| map.borrow().contains(&k)))),
+            (Object::Number(n), Object::Range(range)) => Ok(Object::Bool(range.contains(n))),
+            _ => Err(Error::at(ErrorKind::Type, operator, "Right value must be a list, map, range or string (with a string on the left) for membership tests.".to_string()))
+        },
+        _ => Err(Error::at(ErrorKind::Type, operator, format!("Operator not implemented: {:?}", operator._type)))
+    }
+}
+
+pub fn unary(operator: &Token, child: Object) -> Result<Object, Error> {
+    match operator._type {
+        TokenType::Minus => match child {
+            Object::Number(v) => Ok(Object::Number(-(v))),
+            _ => Err(Error::at(ErrorKind::Type, operator, "Value must be number when negating (-).".to_string()))
+        },
+        TokenType::Not => Ok(Object::Bool(!is_truthy(&child))),
+        _ => Err(Error::at(ErrorKind::Type, operator, format!("Unrecognized unary operator: {:?}", operator._type)))
+    }
+}
+
+// strings aren't repeated into anything longer than this many bytes
+const MAX_REPEAT: usize = 1 << 30;
+
+fn repeat(string: &str, times: f64, operator: &Token) -> Result<Object, Error> {
+    if times < 0.0 || times.fract() != 0.0 {
+        return Err(Error::at(ErrorKind::Value, operator, format!("Strings can only be repeated a whole number of times, not {}.", Object::Number(times))))
+    }
+    match string.len().checked_mul(times as usize) {
+        Some(len) if len <= MAX_REPEAT => Ok(Object::String(string.repeat(times as usize).into())),
+        _ => Err(Error::at(ErrorKind::Value, operator, format!("Repeating a string {} times makes it too long.", Object::Number(times))))
+    }
+}
+
+// `and` and `or` give back one of their operands, not necessarily a bool
+pub fn logical(operator: &Token, l: Object, r: Object) -> Object {
+    let truthy = is_truthy(&l);
+    match operator._type {
+        TokenType::Or if truthy => l,
+        TokenType::Or => r,
+        _ if !truthy => l,
+        _ => r
+    }
+}
+
+pub fn range(operator: &Token, start: Object, end: Object, step: Object, inclusive: bool) -> Result<Object, Error> {
+    match (start, end, step) {
+        (Object::Number(_), Object::Number(_), Object::Number(0.0)) => {
+            Err(Error::at(ErrorKind::Value, operator, "A range's step can't be zero.".to_string()))
+        },
+        (Object::Number(start), Object::Number(end), Object::Number(step)) => {
+            Ok(Object::Range(Range { start, end, step, inclusive }))
+        },
+        _ => Err(Error::at(ErrorKind::Type, operator, "Range bounds and step must all be numbers.".to_string()))
+    }
+}
+
+// `object.name`, binding methods to the instance they're looked up on
+pub fn get(object: Object, name: &Token) -> Result<Object, Error> {
+    match object {
+        Object::Instance(ref instance) => {
+            if let Some(field) = instance.borrow().fields.get(&name.value) {
+                return Ok(field.clone())
+            }
+            if let Some(method) = instance.borrow().class.find_method(&name.value) {
+                return Ok(Object::Function(method.bind(object.clone())))
+            }
+            Err(Error::at(ErrorKind::Attribute, name, format!("Undefined property '{}' on {}.", name.value, object)))
+        },
+        _ => Err(Error::at(ErrorKind::Type, name, format!("Only instances have properties, not {}.", object)))
+    }
+}
+
+pub fn set(object: Object, name: &Token, value: Object) -> Result<(), Error> {
+    match object {
+        Object::Instance(instance) => {
+            instance.borrow_mut().fields.insert(name.value.clone(), value);
+            Ok(())
+        },
+        _ => Err(Error::at(ErrorKind::Type, name, format!("Only instances have fields, not {}.", object)))
+    }
+}
+
+// `super.method`, bound to the `this` of the method it's used in
+pub fn get_super(superclass: &Rc<Class>, this: Object, method: &Token) -> Result<Object, Error> {
+    match superclass.find_method(&method.value) {
+        Some(m) => Ok(Object::Function(m.bind(this))),
+        None => Err(Error::at(ErrorKind::Attribute, method, format!("Undefined property '{}' on superclass {}.", method.value, superclass)))
+    }
+}
+
+pub fn key(key: &Object, token: &Token) -> Result<Key, Error> {
+    match Key::new(key) {
+        Some(k) => Ok(k),
+        None => Err(Error::at(ErrorKind::Type, token, format!("Map keys must be numbers, strings, bools or none, not {}.", key)))
+    }
+}
+
+// turns a (possibly negative) index into a position in a sequence of `len` items
+fn position(index: Object, len: usize, bracket: &Token) -> Result<usize, Error> {
+    let i = match index {
+        Object::Number(n) if n.fract() == 0.0 => n,
+        _ => return Err(Error::at(ErrorKind::Type, bracket, format!("Indices must be whole numbers, not {}.", index)))
+    };
+    let position = if i < 0.0 { len as f64 + i } else { i };
+    if position < 0.0 || position >= len as f64 {
+        return Err(Error::at(ErrorKind::Index, bracket, format!("Index {} is out of range for a length of {}.", i, len)))
+    }
+    Ok(position as usize)
+}
+
+pub fn index(object: Object, i: Object, bracket: &Token) -> Result<Object, Error> {
+    match object {
+        Object::List(list) => {
+            let list = list.borrow();
+            let position = position(i, list.len(), bracket)?;
+            Ok(list[position].clone())
+        },
+        Object::String(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            let position = position(i, chars.len(), bracket)?;
+            Ok(Object::String(chars[position].to_string().into()))
+        },
+        Object::Map(map) => {
+            let key = key(&i, bracket)?;
+            match map.borrow().get(&key) {
+                Some(value) => Ok(value.clone()),
+                None => Err(Error::at(ErrorKind::Key, bracket, format!("Key {} isn't in the map.", repr(&i))))
+            }
+        },
+        _ => Err(Error::at(ErrorKind::Type, bracket, format!("Can only index lists, strings and maps, not {}.", object)))
+    }
+}
+
+// slice bounds are clamped to the sequence, like python's
+fn bound(bound: Option<Object>, default: usize, len: usize, bracket: &Token) -> Result<usize, Error> {
+    let i = match bound {
+        Some(Object::Number(n)) if n.fract() == 0.0 => n,
+        Some(b) => return Err(Error::at(ErrorKind::Type, bracket, format!("Slice bounds must be whole numbers, not {}.", b))),
+        None => return Ok(default)
+    };
+    let position = if i < 0.0 { len as f64 + i } else { i };
+    Ok(position.max(0.0).min(len as f64) as usize)
+}
+
+pub fn slice(object: Object, start: Option<Object>, end: Option<Object>, bracket: &Token) -> Result<Object, Error> {
+    match object {
+        Object::List(list) => {
+            let len = list.borrow().len();
+            let start = bound(start, 0, len, bracket)?;
+            let end = bound(end, len, len, bracket)?.max(start);
+            let slice = list.borrow()[start..end].to_vec();
+            Ok(heap::list(slice))
+        },
+        Object::String(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            let start = bound(start, 0, chars.len(), bracket)?;
+            let end = bound(end, chars.len(), chars.len(), bracket)?.max(start);
+            Ok(Object::String(chars[start..end].iter().collect::<String>().into()))
+        },
+        _ => Err(Error::at(ErrorKind::Type, bracket, format!("Can only slice lists and strings, not {}.", object)))
+    }
+}
+
+pub fn set_index(object: Object, i: Object, value: Object, bracket: &Token) -> Result<(), Error> {
+    match object {
+        Object::List(list) => {
+            let len = list.borrow().len();
+            let position = position(i, len, bracket)?;
+            list.borrow_mut()[position] = value;
+            Ok(())
+        },
+        Object::Map(map) => {
+            let key = key(&i, bracket)?;
+            map.borrow_mut().insert(key, i, value);
+            Ok(())
+        },
+        _ => Err(Error::at(ErrorKind::Type, bracket, format!("Can only assign to indices of lists and maps, not {}.", object)))
+    }
+}
+
+// `throw value` at `keyword`. thrown `Error`s are stamped with where they were thrown
+// from, unless they already say.
+pub fn throw(value: Object, keyword: &Token, error_class: &Rc<Class>) -> Error {
+    if let Object::Instance(instance) = &value {
+        let mut instance = instance.borrow_mut();
+        let mut class = Some(instance.class.clone());
+        while let Some(c) = class {
+            if Rc::ptr_eq(&c, error_class) {
+                instance.fields.entry("line".to_string()).or_insert(Object::Number(keyword.line as f64));
+                instance.fields.entry("column".to_string()).or_insert(Object::Number(keyword.column as f64));
+                break
+            }
+            class = c.superclass.clone();
+        }
+    }
+    Error::thrown(value, keyword)
+}
+
+// what a `catch` sees: the thrown value itself, or an `Error` describing the runtime error
+pub fn error_object(e: RuntimeError, error_class: &Rc<Class>) -> Object {
+    if let Some(value) = e.value {
+        return value
+    }
+    let mut instance = Instance::new(error_class.clone());
+    instance.fields.insert("message".to_string(), Object::String(e.message.into()));
+    instance.fields.insert("kind".to_string(), Object::String(e.kind.to_string().into()));
+    instance.fields.insert("line".to_string(), Object::Number(e.line as f64));
+    instance.fields.insert("column".to_string(), Object::Number(e.column as f64));
+    heap::instance(instance)
+}
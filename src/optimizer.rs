@@ -0,0 +1,408 @@
+use std::{collections::HashSet, rc::Rc};
+
+use crate::{
+    ast::*,
+    token::Span,
+    interpreter::{Object, repr},
+    ops
+};
+
+// Rewrites the tree between parsing and resolving. It folds operators whose operands are
+// constants, drops `if` branches and loops whose condition is a constant that means they never
+// run, drops statements that come after a `return` (or `throw`, `break`, `continue`), and merges
+// blocks into the block around them when that can't change what any name refers to. That last
+// one mostly catches the block a `for` loop gets wrapped in to hold its variable, but only inside
+// a function or block: at the top level the variable would become a global that outlives the
+// loop, so top-level blocks (and top-level `for` loops) are left as they are.
+// It only runs when asked for with `--opt`, since the program has to be resolved once as written
+// (so mistakes in code it drops are still reported) and again after it's been rewritten.
+
+// something the optimizer did, for `--dump-opt`
+pub struct Change {
+    pub span: Span,
+    pub message: String
+}
+
+pub struct Optimizer {
+    pub changes: Vec<Change>
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self { changes: vec![] }
+    }
+
+    pub fn optimize(&mut self, nodes: Vec<Node>) -> Vec<Node> {
+        self.statements(nodes, false)
+    }
+
+    fn change(&mut self, span: Span, message: String) {
+        self.changes.push(Change { span, message });
+    }
+
+    // `scoped` is whether these are a block's statements, which blocks inside can be merged
+    // into. the top level's names are globals, so blocks there stay as they are
+    fn statements(&mut self, nodes: Vec<Node>, scoped: bool) -> Vec<Node> {
+        let mut nodes: Vec<Node> = nodes.into_iter().map(|node| self.node(node)).collect();
+
+        if let Some(i) = nodes.iter().position(|node| matches!(node, Node::Return { .. } | Node::Throw { .. } | Node::Break { .. } | Node::Continue { .. })) {
+            if i + 1 < nodes.len() {
+                let keyword = match &nodes[i] {
+                    Node::Return { .. } => "return",
+                    Node::Throw { .. } => "throw",
+                    Node::Break { .. } => "break",
+                    _ => "continue"
+                };
+                let unreachable: Vec<Node> = nodes.drain(i + 1..).collect();
+                let span = unreachable[0].span().to(&unreachable[unreachable.len() - 1].span());
+                self.change(span, format!("can't be reached after `{}`, so it's removed", keyword));
+            }
+        }
+
+        let mut i = 0;
+        while i < nodes.len() {
+            match &nodes[i] {
+                Node::Block { body, .. } if body.is_empty() => { // what's left of a branch that was dropped
+                    nodes.remove(i);
+                },
+                Node::Block { .. } if scoped && can_merge(&nodes, i) => {
+                    if let Node::Block { span, body } = nodes.remove(i) {
+                        self.change(span, "merged into the block around it".to_string());
+                        let len = body.len();
+                        nodes.splice(i..i, body);
+                        i += len;
+                    }
+                },
+                _ => i += 1
+            }
+        }
+        nodes
+    }
+
+    fn node(&mut self, node: Node) -> Node {
+        match node {
+            Node::BinaryOperator { id, span, left, operator, right } => {
+                let mark = self.changes.len();
+                let (left, right) = (self.node(*left), self.node(*right));
+                if let (Some(l), Some(r)) = (constant(&left), constant(&right)) {
+                    if let Ok(value) = ops::binary(&operator, l, r) {
+                        if let Some(folded) = self.fold(id, span, value, mark) {
+                            return folded
+                        }
+                    }
+                }
+                Node::BinaryOperator { id, span, left: Box::new(left), operator, right: Box::new(right) }
+            },
+            Node::UnaryOperator { id, span, operator, child } => {
+                let mark = self.changes.len();
+                let child = self.node(*child);
+                if let Some(c) = constant(&child) {
+                    if let Ok(value) = ops::unary(&operator, c) {
+                        if let Some(folded) = self.fold(id, span, value, mark) {
+                            return folded
+                        }
+                    }
+                }
+                Node::UnaryOperator { id, span, operator, child: Box::new(child) }
+            },
+            Node::Logical { id, span, left, operator, right } => {
+                let mark = self.changes.len();
+                let (left, right) = (self.node(*left), self.node(*right));
+                if let (Some(l), Some(r)) = (constant(&left), constant(&right)) {
+                    if let Some(folded) = self.fold(id, span, ops::logical(&operator, l, r), mark) {
+                        return folded
+                    }
+                }
+                Node::Logical { id, span, left: Box::new(left), operator, right: Box::new(right) }
+            },
+            Node::Interpolation { id, span, parts } => {
+                let mark = self.changes.len();
+                let parts: Vec<Node> = parts.into_iter().map(|part| self.node(part)).collect();
+                let constants: Option<Vec<Object>> = parts.iter().map(constant).collect();
+                if let Some(constants) = constants {
+                    let string: String = constants.iter().map(|c| c.to_string()).collect();
+                    if let Some(folded) = self.fold(id, span, Object::String(string.into()), mark) {
+                        return folded
+                    }
+                }
+                Node::Interpolation { id, span, parts }
+            },
+
+            Node::If { id, span, condition, body, else_block } => {
+                let condition = self.node(*condition);
+                match constant(&condition).map(|c| ops::is_truthy(&c)) {
+                    Some(true) if !else_block.as_deref().is_some_and(declares) => {
+                        self.change(span, "condition is always true, so only the body is kept".to_string());
+                        self.node(*body)
+                    },
+                    Some(false) if !declares(&body) => match else_block {
+                        Some(else_block) => {
+                            self.change(span, "condition is always false, so only the else branch is kept".to_string());
+                            self.node(*else_block)
+                        },
+                        None => {
+                            self.change(span, "condition is always false, so it's removed".to_string());
+                            Node::Block { span, body: vec![] }
+                        }
+                    },
+                    _ => Node::If {
+                        id,
+                        span,
+                        condition: Box::new(condition),
+                        body: Box::new(self.node(*body)),
+                        else_block: else_block.map(|e| Box::new(self.node(*e)))
+                    }
+                }
+            },
+            Node::While { id, span, condition, body, increment } => {
+                let condition = self.node(*condition);
+                if constant(&condition).is_some_and(|c| !ops::is_truthy(&c)) && !declares(&body) {
+                    self.change(span, "condition is always false, so the loop is removed".to_string());
+                    return Node::Block { span, body: vec![] }
+                }
+                Node::While {
+                    id,
+                    span,
+                    condition: Box::new(condition),
+                    body: Box::new(self.node(*body)),
+                    increment: increment.map(|i| Box::new(self.node(*i)))
+                }
+            },
+            Node::ForIn { id, span, variable, iterable, body } => {
+                Node::ForIn { id, span, variable, iterable: Box::new(self.node(*iterable)), body: Box::new(self.node(*body)) }
+            },
+            Node::Block { span, body } => Node::Block { span, body: self.statements(body, true) },
+
+            Node::Declare { id, span, name, value } => Node::Declare { id, span, name, value: Box::new(self.node(*value)) },
+            Node::Assign { id, span, name, value } => Node::Assign { id, span, name, value: Box::new(self.node(*value)) },
+            Node::DeclareFn { id, span, name, args, body } => {
+                let (args, body) = self.function(args, body);
+                Node::DeclareFn { id, span, name, args, body }
+            },
+            Node::Lambda { id, span, keyword, args, body } => {
+                let (args, body) = self.function(args, body);
+                Node::Lambda { id, span, keyword, args, body }
+            },
+            Node::Class { id, span, name, superclass, methods } => Node::Class {
+                id,
+                span,
+                name,
+                superclass: superclass.map(|s| Box::new(self.node(*s))),
+                methods: methods.into_iter().map(|m| self.node(m)).collect()
+            },
+            Node::Return { id, span, keyword, value } => Node::Return { id, span, keyword, value: Box::new(self.node(*value)) },
+            Node::Throw { id, span, keyword, value } => Node::Throw { id, span, keyword, value: Box::new(self.node(*value)) },
+            Node::Try { id, span, body, catch, finally } => Node::Try {
+                id,
+                span,
+                body: Box::new(self.node(*body)),
+                catch: catch.map(|(name, handler)| (name, Box::new(self.node(*handler)))),
+                finally: finally.map(|f| Box::new(self.node(*f)))
+            },
+
+            Node::Range { id, span, start, end, step, inclusive, operator } => Node::Range {
+                id,
+                span,
+                start: Box::new(self.node(*start)),
+                end: Box::new(self.node(*end)),
+                step: step.map(|s| Box::new(self.node(*s))),
+                inclusive,
+                operator
+            },
+            Node::FnCall { id, span, name, args, kwargs, paren } => Node::FnCall {
+                id,
+                span,
+                name: Box::new(self.node(*name)),
+                args: args.into_iter().map(|a| self.node(a)).collect(),
+                kwargs: kwargs.into_iter().map(|(k, v)| (k, self.node(v))).collect(),
+                paren
+            },
+            Node::Get { id, span, object, name } => Node::Get { id, span, object: Box::new(self.node(*object)), name },
+            Node::Set { id, span, object, name, value } => {
+                Node::Set { id, span, object: Box::new(self.node(*object)), name, value: Box::new(self.node(*value)) }
+            },
+            Node::List { id, span, items } => Node::List { id, span, items: items.into_iter().map(|i| self.node(i)).collect() },
+            Node::Map { id, span, entries, brace } => Node::Map {
+                id,
+                span,
+                entries: entries.into_iter().map(|(k, v)| (self.node(k), self.node(v))).collect(),
+                brace
+            },
+            Node::Index { id, span, object, index, bracket } => {
+                Node::Index { id, span, object: Box::new(self.node(*object)), index: Box::new(self.node(*index)), bracket }
+            },
+            Node::Slice { id, span, object, start, end, bracket } => Node::Slice {
+                id,
+                span,
+                object: Box::new(self.node(*object)),
+                start: start.map(|s| Box::new(self.node(*s))),
+                end: end.map(|e| Box::new(self.node(*e))),
+                bracket
+            },
+            Node::SetIndex { id, span, object, index, value, bracket } => Node::SetIndex {
+                id,
+                span,
+                object: Box::new(self.node(*object)),
+                index: Box::new(self.node(*index)),
+                value: Box::new(self.node(*value)),
+                bracket
+            },
+
+            leaf => leaf // literals, variables, `this`, `super`, `break` and `continue`
+        }
+    }
+
+    // the parser just made these, so nothing else holds them yet
+    fn function(&mut self, args: Rc<Vec<Param>>, body: Rc<Node>) -> (Rc<Vec<Param>>, Rc<Node>) {
+        let args = Rc::try_unwrap(args).unwrap_or_else(|args| (*args).clone());
+        let args = args.into_iter().map(|p| Param { default: p.default.map(|d| self.node(d)), ..p }).collect();
+        let body = Rc::try_unwrap(body).unwrap_or_else(|body| (*body).clone());
+        (Rc::new(args), Rc::new(self.node(body)))
+    }
+
+    // a literal in place of the expression at `span`. only the outermost fold is reported, so
+    // the ones made inside it since `mark` are dropped
+    fn fold(&mut self, id: usize, span: Span, value: Object, mark: usize) -> Option<Node> {
+        let literal = match &value {
+            Object::Number(n) => Literal::Number(*n),
+            Object::Bool(b) => Literal::Bool(*b),
+            Object::String(s) => Literal::String(s.to_string()),
+            Object::None => Literal::None,
+            _ => return None
+        };
+        self.changes.truncate(mark);
+        self.change(span, format!("folded into {}", repr(&value)));
+        Some(Node::Literal { id, span, value: literal })
+    }
+}
+
+fn constant(node: &Node) -> Option<Object> {
+    match node {
+        Node::Literal { value, .. } => Some(match value {
+            Literal::Number(n) => Object::Number(*n),
+            Literal::Bool(b) => Object::Bool(*b),
+            Literal::String(s) => Object::String(s.as_str().into()),
+            Literal::None => Object::None
+        }),
+        _ => None
+    }
+}
+
+// the names a list of statements declares in the scope they're in
+fn declared(nodes: &[Node]) -> HashSet<&str> {
+    nodes.iter().filter_map(|node| match node {
+        Node::Declare { name, .. } | Node::DeclareFn { name, .. } | Node::Class { name, .. } => Some(name.value.as_str()),
+        _ => None
+    }).collect()
+}
+
+// whether a branch that isn't a block declares a name in the scope around it. the name exists
+// (as `none`) even when the branch never runs, so the branch can't be dropped
+fn declares(node: &Node) -> bool {
+    match node {
+        Node::Declare { .. } | Node::DeclareFn { .. } | Node::Class { .. } => true,
+        Node::If { body, else_block, .. } => declares(body) || else_block.as_deref().is_some_and(declares),
+        Node::While { body, .. } => declares(body),
+        _ => false
+    }
+}
+
+// a block can join the scope around it when it declares nothing that scope does (which would
+// make the two the same variable) and nothing after it mentions a name it declares (which
+// would then see the block's variable instead of whatever it saw before)
+fn can_merge(nodes: &[Node], i: usize) -> bool {
+    let inner = match &nodes[i] {
+        Node::Block { body, .. } => declared(body),
+        _ => return false
+    };
+    let outer: HashSet<&str> = declared(&nodes[..i]).union(&declared(&nodes[i + 1..])).copied().collect();
+    if !inner.is_disjoint(&outer) {
+        return false
+    }
+
+    let mut mentioned = false;
+    for node in &nodes[i + 1..] {
+        walk(node, &mut |n| match n {
+            Node::Variable { name, .. } | Node::Assign { name, .. } => mentioned |= inner.contains(name.value.as_str()),
+            _ => {}
+        });
+    }
+    !mentioned
+}
+
+// calls `f` on `node` and everything inside it, function bodies included
+fn walk(node: &Node, f: &mut dyn FnMut(&Node)) {
+    f(node);
+    for child in children(node) {
+        walk(child, f);
+    }
+}
+
+fn children(node: &Node) -> Vec<&Node> {
+    let mut children: Vec<&Node> = vec![];
+    match node {
+        Node::BinaryOperator { left, right, .. } | Node::Logical { left, right, .. } => children.extend([&**left, &**right]),
+        Node::UnaryOperator { child, .. } => children.push(child),
+        Node::Declare { value, .. } | Node::Assign { value, .. } | Node::Return { value, .. } | Node::Throw { value, .. } => children.push(value),
+        Node::If { condition, body, else_block, .. } => {
+            children.extend([&**condition, &**body]);
+            children.extend(else_block.as_deref());
+        },
+        Node::While { condition, body, increment, .. } => {
+            children.extend([&**condition, &**body]);
+            children.extend(increment.as_deref());
+        },
+        Node::ForIn { iterable, body, .. } => children.extend([&**iterable, &**body]),
+        Node::Range { start, end, step, .. } => {
+            children.extend([&**start, &**end]);
+            children.extend(step.as_deref());
+        },
+        Node::DeclareFn { args, body, .. } | Node::Lambda { args, body, .. } => {
+            children.extend(args.iter().filter_map(|a| a.default.as_ref()));
+            children.push(body);
+        },
+        Node::FnCall { name, args, kwargs, .. } => {
+            children.push(name);
+            children.extend(args);
+            children.extend(kwargs.iter().map(|(_, v)| v));
+        },
+        Node::Try { body, catch, finally, .. } => {
+            children.push(body);
+            children.extend(catch.as_ref().map(|(_, handler)| &**handler));
+            children.extend(finally.as_deref());
+        },
+        Node::Class { superclass, methods, .. } => {
+            children.extend(superclass.as_deref());
+            children.extend(methods);
+        },
+        Node::Get { object, .. } => children.push(object),
+        Node::Set { object, value, .. } => children.extend([&**object, &**value]),
+        Node::Interpolation { parts: items, .. } | Node::List { items, .. } | Node::Block { body: items, .. } => children.extend(items),
+        Node::Map { entries, .. } => children.extend(entries.iter().flat_map(|(k, v)| [k, v])),
+        Node::Index { object, index, .. } => children.extend([&**object, &**index]),
+        Node::Slice { object, start, end, .. } => {
+            children.push(object);
+            children.extend(start.as_deref());
+            children.extend(end.as_deref());
+        },
+        Node::SetIndex { object, index, value, .. } => children.extend([&**object, &**index, &**value]),
+        Node::Literal { .. } | Node::Variable { .. } | Node::This { .. } | Node::Super { .. } |
+        Node::Break { .. } | Node::Continue { .. } => {}
+    }
+    children
+}
+
+// how `--dump-opt` shows a change: where it was, the first line of the code it changed, and what happened
+pub fn describe(change: &Change, source: &str) -> String {
+    let code = source.get(change.span.start..change.span.end).unwrap_or("");
+    let mut lines = code.lines();
+    let first = lines.next().unwrap_or("").trim_end();
+    let more = if lines.next().is_some() { " ..." } else { "" };
+    format!("[{}:{}] `{}{}` {}", change.span.line, change.span.column, first, more, change.message)
+}
@@ -1,16 +1,35 @@
+use std::rc::Rc;
 use crate::token::*;
 use crate::ast::*;
 use crate::error::Error;
+use crate::diagnostic::Diagnostic;
+
+fn is_trivia(token: &Token) -> bool {
+    matches!(token._type, TokenType::DocComment(_))
+}
 
 pub struct Parser<'a> {
     tokens:  &'a Vec<Token>,
     token_index: usize,
-    current_id: usize
+    current_id: usize,
+    pub trailing: bool, // whether an expression at the very end can leave off its `;`, like at the prompt
+    errors: Vec<Diagnostic> // syntax errors recovered from so far
 }
 
 impl <'a>Parser<'a> {
     pub fn new(tokens: &'a Vec<Token>) -> Self {
-        Self {tokens, token_index: 0, current_id: 0}
+        Self::with_id(tokens, 0)
+    }
+
+    // starts numbering nodes after `current_id` so ids from earlier parses aren't reused
+    pub fn with_id(tokens: &'a Vec<Token>, current_id: usize) -> Self {
+        let mut parser = Self {tokens, token_index: 0, current_id, trailing: false, errors: vec![]};
+        parser.skip_trivia();
+        parser
+    }
+
+    pub fn current_id(&self) -> usize {
+        self.current_id
     }
 
     fn current(&self) -> &Token {
@@ -18,13 +37,31 @@ impl <'a>Parser<'a> {
         &token.unwrap()
     }
 
+    // from `start` to the end of the last token parsed
+    fn span_from(&self, start: Span) -> Span {
+        start.to(&self.previous().map_or(start, |t| t.span()))
+    }
+
+    // the last real token before the current one
+    fn previous(&self) -> Option<&Token> {
+        self.tokens[..self.token_index].iter().rev().find(|t| !is_trivia(t))
+    }
+
     fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.token_index + 1)
+        self.tokens[self.token_index + 1..].iter().find(|t| !is_trivia(t))
     }
 
     fn next(&mut self) {
         if !matches!(self.current()._type, TokenType::Eof) {
             self.token_index += 1;
+            self.skip_trivia();
+        }
+    }
+
+    // doc comments are kept in the token stream for tooling, but mean nothing to the parser
+    fn skip_trivia(&mut self) {
+        while is_trivia(self.current()) {
+            self.token_index += 1;
         }
     }
 
@@ -34,54 +71,127 @@ impl <'a>Parser<'a> {
         id + 1
     }
 
-    #[allow(unused_variables)] // for some reason my IDE complains about unused variables
+    // moves past the current token if it's an `expected` (any name, for `Name`)
     fn eat(&mut self, expected: &TokenType, msg: &str) -> Result<(), Error> {
-        match &self.current()._type {
-            &TokenType::Name(_) => {
-                match expected {
-                    &TokenType::Name(_) => {
-                        return Ok(self.next());
-                    }
-                    _ => return Err(Error::Syntax(format!("{} [{}:{}]", msg, self.current().line, self.current().column)))
-                }
-            }
-            _ => {
-                match expected {
-                    &TokenType::Name(_) => {
-                        panic!("{}", msg)
-                    },
-                    _ => {
-                        if &self.current()._type == expected {
-                            return Ok(self.next())
-                        } else {
-                            return Err(Error::Syntax(format!("{} [{}:{}]", msg, self.current().line, self.current().column)))
-                        }
-                    }
-                }
+        let found = &self.current()._type;
+        let matches = match expected {
+            TokenType::Name(_) => matches!(found, TokenType::Name(_)),
+            _ => found == expected
+        };
+        if !matches {
+            return Err(Error::Syntax(self.unexpected(expected, msg)))
+        }
+        self.next();
+        Ok(())
+    }
+
+    fn unexpected(&self, expected: &TokenType, msg: &str) -> Diagnostic {
+        let current = self.current();
+        // a missing separator belongs just after the previous token, not on the next line
+        if expected == &TokenType::Separate {
+            if let Some(previous) = self.previous().filter(|p| !p.value.contains('\n')) {
+                return Diagnostic::new("E0101", msg.to_string(), previous.line, previous.column + previous.value.chars().count())
+                    .help("add a `;` here".to_string())
             }
         }
+
+        let found = match current._type {
+            TokenType::Eof => "the end of the file".to_string(),
+            _ => format!("'{}'", current.value)
+        };
+        let diagnostic = Diagnostic::at("E0100", msg.to_string(), current).note(format!("found {}", found));
+        match (&current._type, expected) {
+            (TokenType::Assign, TokenType::ParClose) => diagnostic.help("did you mean `==`?".to_string()),
+            (TokenType::Equal, TokenType::Assign) => diagnostic.help("did you mean `=`?".to_string()),
+            _ => diagnostic
+        }
     }
 
+    // fails with every syntax error in the file, not just the first
     pub fn parse(&mut self) -> Result<Vec<Node>, Error> {
-        let mut nodes = Vec::<Node>::new();
+        let (nodes, mut errors) = self.parse_recovering();
+        match errors.len() {
+            0 => Ok(nodes),
+            1 => Err(Error::Syntax(errors.remove(0))),
+            _ => Err(Error::Parse(errors))
+        }
+    }
 
+    // the statements that parsed, and a diagnostic for each one that didn't. meant for
+    // tooling, which wants as much of the tree as it can get even from a broken file.
+    pub fn parse_recovering(&mut self) -> (Vec<Node>, Vec<Diagnostic>) {
+        let mut nodes = Vec::<Node>::new();
         while self.current()._type != TokenType::Eof {
-            nodes.push(self.statement()?);
+            if let Some(node) = self.recover(|p| p.statement()) {
+                nodes.push(node);
+            }
         }
+        (nodes, std::mem::take(&mut self.errors))
+    }
 
-        Ok(nodes)
+    // runs `parse`, and on a syntax error records it and skips ahead to somewhere parsing
+    // can sensibly pick up again
+    fn recover(&mut self, parse: impl FnOnce(&mut Self) -> Result<Node, Error>) -> Option<Node> {
+        let start = self.token_index;
+        let error = match parse(self) {
+            Ok(node) => return Some(node),
+            Err(error) => error
+        };
+        match error {
+            // the statement was fine up to where its `;` should have been, so carry on from there
+            Error::Syntax(d) if d.code == "E0101" => self.errors.push(d),
+            Error::Syntax(d) => {
+                self.errors.push(d);
+                self.synchronize();
+            },
+            Error::Parse(ds) => {
+                self.errors.extend(ds);
+                self.synchronize();
+            },
+            _ => {}
+        }
+        if self.token_index == start { // e.g. a stray `}`, which nothing will ever consume
+            self.next();
+        }
+        None
+    }
+
+    // panic mode: skip tokens until just after a `;`, or just before a `}` or the keyword
+    // starting the next statement. blocks opened along the way are skipped whole.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.current()._type {
+                TokenType::Eof => return,
+                TokenType::BrackOpen => depth += 1,
+                TokenType::BrackClose if depth == 0 => return,
+                TokenType::BrackClose => depth -= 1,
+                TokenType::Separate if depth == 0 => {
+                    self.next();
+                    return
+                },
+                TokenType::Declare | TokenType::FuncDeclare | TokenType::Class | TokenType::If |
+                TokenType::While | TokenType::For | TokenType::Return | TokenType::Break |
+                TokenType::Continue | TokenType::Try | TokenType::Throw if depth == 0 => return,
+                _ => {}
+            }
+            self.next();
+        }
     }
 
     fn code_block(&mut self) -> Result<Node, Error> {
+        let start = self.current().span();
         let mut nodes = Vec::<Node>::new();
         self.eat(&TokenType::BrackOpen, "Expected open bracket to code block.")?;
 
-        while self.current()._type != TokenType::BrackClose {
-            nodes.push(self.statement()?);
+        while !matches!(self.current()._type, TokenType::BrackClose | TokenType::Eof) {
+            if let Some(node) = self.recover(|p| p.statement()) {
+                nodes.push(node);
+            }
         }
 
         self.eat(&TokenType::BrackClose, "Expected closing bracket to code block.")?;
-        Ok(Node::Block(nodes))
+        Ok(Node::Block { span: self.span_from(start), body: nodes })
     }
 
     fn statement(&mut self) -> Result<Node, Error> {
@@ -92,27 +202,91 @@ impl <'a>Parser<'a> {
                 if peek != None && matches!(peek.unwrap()._type, TokenType::Assign) { // TODO: increment & decrement
                     self.assign()
                 } else {
-                    let expr = self.get_expression()?;
-                    self.eat(&TokenType::Separate, "Expected a separator after the statement.")?;
-                    Ok(expr)
+                    self.expression_statement()
                 }
             },
             TokenType::If => self.if_statement(),
             TokenType::While => self.while_statement(),
             TokenType::For => self.for_statement(),
             TokenType::BrackOpen => self.code_block(),
-            TokenType::FuncDeclare => self.declare_fn(),
+            TokenType::FuncDeclare => {
+                let peek = self.peek();
+                if peek != None && peek.unwrap()._type == TokenType::ParOpen { // an anonymous function
+                    self.expression_statement()
+                } else {
+                    self.declare_fn()
+                }
+            },
+            TokenType::Class => self.declare_class(),
             TokenType::Return => self.return_statement(),
-            _ => { 
-                let expr = self.get_expression()?;
-                self.eat(&TokenType::Separate, "Expected a separator after the statement.")?;
-                Ok(expr)
+            TokenType::Throw => {
+                let keyword = self.current().clone();
+                self.next();
+                let value = Box::new(self.get_expression()?);
+                self.eat(&TokenType::Separate, "Expected a separator after throw statement.")?;
+                Ok(Node::Throw { id: self.new_id(), span: self.span_from(keyword.span()), keyword, value })
+            },
+            TokenType::Try => self.try_statement(),
+            TokenType::Break => {
+                let keyword = self.current().clone();
+                self.next();
+                self.eat(&TokenType::Separate, "Expected a separator after 'break'.")?;
+                Ok(Node::Break { id: self.new_id(), span: self.span_from(keyword.span()), keyword })
+            },
+            TokenType::Continue => {
+                let keyword = self.current().clone();
+                self.next();
+                self.eat(&TokenType::Separate, "Expected a separator after 'continue'.")?;
+                Ok(Node::Continue { id: self.new_id(), span: self.span_from(keyword.span()), keyword })
+            },
+            _ => self.expression_statement()
+        }
+    }
+
+    // an expression on its own, or an assignment to an index or property of one
+    // (`xs[i] = v;`, `p.x = v;`)
+    fn expression_statement(&mut self) -> Result<Node, Error> {
+        let expr = self.get_expression()?;
+
+        if self.current()._type == TokenType::Assign {
+            let equals = self.current().clone();
+            self.next();
+            let value = self.get_expression()?;
+            self.eat(&TokenType::Separate, "Expected a separator after the statement.")?;
+
+            let span = self.span_from(expr.span());
+            return match expr {
+                Node::Index { object, index, bracket, .. } => Ok(Node::SetIndex {
+                    id: self.new_id(),
+                    span,
+                    object,
+                    index,
+                    value: Box::new(value),
+                    bracket
+                }),
+                Node::Get { object, name, .. } => Ok(Node::Set {
+                    id: self.new_id(),
+                    span,
+                    object,
+                    name,
+                    value: Box::new(value)
+                }),
+                _ => Err(Error::Syntax(Diagnostic::at("E0102", "Invalid assignment target.".to_string(), &equals)))
             }
         }
+
+        if !(self.trailing && self.current()._type == TokenType::Eof) {
+            self.eat(&TokenType::Separate, "Expected a separator after the statement.")?;
+        }
+        Ok(expr)
     }
 
     fn for_statement(&mut self) -> Result<Node, Error> {
+        let start = self.current().span();
         self.eat(&TokenType::For, "")?;
+        if matches!(self.current()._type, TokenType::Name(_)) {
+            return self.for_in_statement(start)
+        }
         self.eat(&TokenType::ParOpen, "Expected open parenthesis to for loop initializer")?;
 
         let var = self.declare_var()?;
@@ -121,88 +295,235 @@ impl <'a>Parser<'a> {
         let condition = Box::new(self.get_expression()?);
         self.eat(&TokenType::Separate, "Expected separator after for loop condition.")?;
 
-        let increment = self.assign()?;
-        // self.eat(&TokenType::Separate, "Expected separator after for loop increment.")?;
+        let increment = self.assignment()?;
+        if self.current()._type == TokenType::Separate { // older scripts end the increment with a separator
+            self.next();
+        }
         self.eat(&TokenType::ParClose, "Expected close parenthesis to for loop initializer")?;
         
-        let body = Box::new(Node::Block(vec![self.statement()?, increment]));
+        let body = Box::new(self.statement()?);
         
+        let span = self.span_from(start);
         let while_loop = Node::While {
             id: self.new_id(),
+            span,
             condition,
-            body
+            body,
+            increment: Some(Box::new(increment))
         };
-        Ok(Node::Block(vec![var, while_loop]))
+        Ok(Node::Block { span, body: vec![var, while_loop] })
+    }
+
+    // `for item in iterable body`
+    fn for_in_statement(&mut self, start: Span) -> Result<Node, Error> {
+        let variable = self.current().clone();
+        self.eat(&TokenType::Name("".to_string()), "Expected a loop variable after 'for'.")?;
+        self.eat(&TokenType::In, "Expected 'in' after for loop variable.")?;
+        let iterable = Box::new(self.get_expression()?);
+        let body = Box::new(self.statement()?);
+        Ok(Node::ForIn { id: self.new_id(), span: self.span_from(start), variable, iterable, body })
+    }
+
+    fn declare_class(&mut self) -> Result<Node, Error> {
+        let start = self.current().span();
+        self.eat(&TokenType::Class, "")?;
+        let name = self.current().clone();
+        self.eat(&TokenType::Name("".to_string()), "Expected a name for the class declaration.")?;
+
+        let mut superclass = None;
+        if self.current()._type == TokenType::Less {
+            self.next();
+            let name = self.current().clone();
+            self.eat(&TokenType::Name("".to_string()), "Expected a superclass name after '<'.")?;
+            superclass = Some(Box::new(Node::Variable { id: self.new_id(), span: name.span(), name }));
+        }
+
+        self.eat(&TokenType::BrackOpen, "Expected '{' before class body.")?;
+
+        let mut methods = Vec::<Node>::new();
+        while !matches!(self.current()._type, TokenType::BrackClose | TokenType::Eof) {
+            if self.current()._type != TokenType::FuncDeclare {
+                return Err(Error::Syntax(Diagnostic::at("E0100", "Expected a method declaration in class body.".to_string(), self.current())))
+            }
+            methods.push(self.declare_fn()?);
+        }
+
+        self.eat(&TokenType::BrackClose, "Expected '}' after class body.")?;
+        Ok(Node::Class { id: self.new_id(), span: self.span_from(start), name, superclass, methods })
     }
 
     fn return_statement(&mut self) -> Result<Node, Error> {
+        let keyword = self.current().clone();
         self.eat(&TokenType::Return, "")?;
-        let value = self.get_expression()?;
+        let value = if self.current()._type == TokenType::Separate { // a bare `return;` gives back none
+            Node::Literal { id: self.new_id(), span: keyword.span(), value: Literal::None }
+        } else {
+            self.get_expression()?
+        };
         self.eat(&TokenType::Separate, "Expected separator after return statement.")?;
         Ok(Node::Return {
             id: self.new_id(),
+            span: self.span_from(keyword.span()),
+            keyword,
             value: Box::new(value)
         })
     }
 
+    fn try_statement(&mut self) -> Result<Node, Error> {
+        let keyword = self.current().clone();
+        self.eat(&TokenType::Try, "")?;
+        let body = Box::new(self.code_block()?);
+
+        let mut catch = None;
+        if self.current()._type == TokenType::Catch {
+            self.next();
+            self.eat(&TokenType::ParOpen, "Expected '(' after 'catch'.")?;
+            let name = self.current().clone();
+            self.eat(&TokenType::Name("".to_string()), "Expected a name for the caught error.")?;
+            self.eat(&TokenType::ParClose, "Expected ')' after the caught error's name.")?;
+            catch = Some((name, Box::new(self.code_block()?)));
+        }
+
+        let mut finally = None;
+        if self.current()._type == TokenType::Finally {
+            self.next();
+            finally = Some(Box::new(self.code_block()?));
+        }
+
+        if catch.is_none() && finally.is_none() {
+            return Err(Error::Syntax(Diagnostic::at("E0106", "Expected 'catch' or 'finally' after try block.".to_string(), &keyword)))
+        }
+        Ok(Node::Try { id: self.new_id(), span: self.span_from(keyword.span()), body, catch, finally })
+    }
+
     fn declare_fn(&mut self) -> Result<Node, Error> {
+        let start = self.current().span();
         self.eat(&TokenType::FuncDeclare, "")?;
         let name = self.current().clone();
 
         self.eat(&TokenType::Name("".to_string()), "Expected a name for the function declaration.")?;
         self.eat(&TokenType::ParOpen, "Expected an open parenthesis for the function declaration.")?;
+        let args = self.parameters(&TokenType::ParClose)?;
+        self.eat(&TokenType::ParClose, "Expected closing parenthesis to function declaration.")?;
 
-        let mut args = Vec::<Token>::new();
-        if self.current()._type != TokenType::ParClose {
-            args.push(self.current().clone());
+        let body = self.statement()?;
+        Ok(Node::DeclareFn {
+            id: self.new_id(),
+            span: self.span_from(start),
+            name,
+            args: Rc::new(args),
+            body: Rc::new(body)
+        })
+    }
+
+    // a comma separated list of parameters, up to (but not including) `closing`
+    fn parameters(&mut self, closing: &TokenType) -> Result<Vec<Param>, Error> {
+        let mut args = Vec::<Param>::new();
+        while &self.current()._type != closing {
+            let variadic = self.current()._type == TokenType::Ellipsis;
+            if variadic {
+                self.next();
+            }
+            let name = self.current().clone();
             self.eat(&TokenType::Name("".to_string()), "Expected a name for function arguments.")?;
 
-            while self.current()._type == TokenType::Comma {
-                self.eat(&TokenType::Comma, "")?;
-                args.push(self.current().clone());
-                self.eat(&TokenType::Name("".to_string()), "")?;
+            let mut default = None;
+            if self.current()._type == TokenType::Assign {
+                if variadic {
+                    return Err(Error::Syntax(Diagnostic::at("E0103", format!("Variadic parameter '{}' cannot have a default value.", name.value), &name)))
+                }
+                self.next();
+                default = Some(self.get_expression()?);
             }
 
-            if args.len() > 150 {
-                return Err(Error::Syntax(format!("Cannot have more than 150 arguments in a function declaration. [{}:{}]", self.current().line, self.current().column)));
+            if let Some(last) = args.last() {
+                if last.variadic {
+                    return Err(Error::Syntax(Diagnostic::at("E0103", format!("Variadic parameter '{}' must be the last parameter.", last.name.value), &last.name)))
+                }
+                if last.default.is_some() && default.is_none() && !variadic {
+                    return Err(Error::Syntax(Diagnostic::at("E0103", format!("Parameter '{}' without a default value follows one with a default.", name.value), &name)))
+                }
+            }
+            if args.iter().any(|a| a.name.value == name.value) {
+                return Err(Error::Syntax(Diagnostic::at("E0103", format!("Duplicate parameter '{}'.", name.value), &name)))
             }
+            args.push(Param { name, default, variadic });
 
+            if self.current()._type != TokenType::Comma {
+                break
+            }
+            self.eat(&TokenType::Comma, "")?;
         }
 
-        self.eat(&TokenType::ParClose, "Expected closing parenthesis to function declaration.")?;
-        let body = self.statement()?;
-        Ok(Node::DeclareFn {
-            id: self.new_id(),
-            name,
-            args,
-            body: Box::new(body)
-        })
+        if args.len() > 150 {
+            return Err(Error::Syntax(Diagnostic::at("E0103", "Cannot have more than 150 arguments in a function declaration.".to_string(), self.current())));
+        }
+        Ok(args)
+    }
+
+    // `fn (a, b) { ... }`, or `|a, b| expression` which returns the expression. arrow
+    // functions can also take a block (`|x| { ... }`), so one that returns a map needs
+    // parentheses around it.
+    fn lambda(&mut self) -> Result<Node, Error> {
+        let mut keyword = self.current().clone();
+        keyword.value = "<lambda>".to_string();
+
+        if self.current()._type == TokenType::FuncDeclare {
+            self.next();
+            self.eat(&TokenType::ParOpen, "Expected an open parenthesis for the function.")?;
+            let args = self.parameters(&TokenType::ParClose)?;
+            self.eat(&TokenType::ParClose, "Expected closing parenthesis to function.")?;
+            let body = self.code_block()?;
+            return Ok(Node::Lambda { id: self.new_id(), span: self.span_from(keyword.span()), keyword, args: Rc::new(args), body: Rc::new(body) })
+        }
+
+        self.eat(&TokenType::Pipe, "Expected '|' to start a function.")?;
+        let args = self.parameters(&TokenType::Pipe)?;
+        self.eat(&TokenType::Pipe, "Expected '|' after function arguments.")?;
+
+        let body = if self.current()._type == TokenType::BrackOpen {
+            self.code_block()?
+        } else {
+            let value = self.get_expression()?;
+            Node::Return { id: self.new_id(), span: value.span(), keyword: keyword.clone(), value: Box::new(value) }
+        };
+        Ok(Node::Lambda { id: self.new_id(), span: self.span_from(keyword.span()), keyword, args: Rc::new(args), body: Rc::new(body) })
     }
 
     pub fn declare_var(&mut self) -> Result<Node, Error> {
+        let start = self.current().span();
         self.eat(&TokenType::Declare, "Expected 'let' in front of variable declaration.")?;
         let name = self.current().clone();
 
         self.eat(&TokenType::Name("".to_string()), "Expected a name after 'let' keyword.")?;
-        self.eat(&TokenType::Assign, format!("Expected '=' to assign '{:?}' to a value", name).as_str())?;
+        self.eat(&TokenType::Assign, format!("Expected '=' to assign a value to '{}'.", name.value).as_str())?;
 
         let value = self.get_expression()?;
         self.eat(&TokenType::Separate, "Expected a separator after the statement.")?;
 
-        Ok(Node::Declare { name, value: Box::new(value), id: self.new_id() })
+        Ok(Node::Declare { name, value: Box::new(value), id: self.new_id(), span: self.span_from(start) })
     }
 
     pub fn assign(&mut self) -> Result<Node, Error> {
+        let assignment = self.assignment()?;
+        self.eat(&TokenType::Separate, "Expected a separator after the statement.")?;
+        match assignment {
+            Node::Assign { id, span, name, value } => Ok(Node::Assign { id, span: self.span_from(span), name, value }),
+            other => Ok(other)
+        }
+    }
+
+    fn assignment(&mut self) -> Result<Node, Error> {
         let name = self.current().clone();
         self.eat(&TokenType::Name("".to_string()), "Expected a name when assigning a value.")?;
-        self.eat(&TokenType::Assign, format!("Expected '=' to assign '{:?}' to a value", name).as_str())?;
+        self.eat(&TokenType::Assign, format!("Expected '=' to assign a value to '{}'.", name.value).as_str())?;
         let value = self.get_expression()?;
-        self.eat(&TokenType::Separate, "Expected a separator after the statement.")?;
 
-        Ok(Node::Assign {name, value: Box::new(value), id: self.new_id() })
+        Ok(Node::Assign { span: self.span_from(name.span()), name, value: Box::new(value), id: self.new_id() })
     }
 
     pub fn if_statement(&mut self) -> Result<Node, Error> {
+        let start = self.current().span();
         self.eat(&TokenType::If, "Expected 'if' to starting if statement.")?;
         self.eat(&TokenType::ParOpen, "Expected open parenthesis to if statement.")?;
 
@@ -221,18 +542,20 @@ impl <'a>Parser<'a> {
             condition: Box::new(condition),
             body: Box::new(body),
             else_block,
-            id: self.new_id()
+            id: self.new_id(),
+            span: self.span_from(start)
         })
 
     }
 
     fn while_statement(&mut self) -> Result<Node, Error> {
+        let start = self.current().span();
         self.eat(&TokenType::While, "Expected 'while' to start of while loop.")?;
         self.eat(&TokenType::ParOpen, "Expected '(' to condition of while loop.")?;
         let condition = Box::new(self.get_expression()?);
         self.eat(&TokenType::ParClose, "Expected ')' after condition of while loop.")?;
         let body = Box::new(self.statement()?);
-        Ok(Node::While {condition, body, id: self.new_id() })
+        Ok(Node::While { condition, body, increment: None, id: self.new_id(), span: self.span_from(start) })
     }
 
     pub fn get_expression(&mut self) -> Result<Node, Error> {
@@ -248,6 +571,7 @@ impl <'a>Parser<'a> {
             let right = self.and_statement()?;
 
             expr = Node::Logical {
+                span: expr.span().to(&right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -267,6 +591,7 @@ impl <'a>Parser<'a> {
             let right = self.equality()?;
 
             expr = Node::Logical {
+                span: expr.span().to(&right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -286,8 +611,9 @@ impl <'a>Parser<'a> {
             let right = self.comparison()?;
 
             expr = Node::BinaryOperator {
+                span: expr.span().to(&right.span()),
                 left: Box::new(expr),
-                operator: operator,
+                operator,
                 right: Box::new(right),
                 id: self.new_id()
             }
@@ -297,19 +623,21 @@ impl <'a>Parser<'a> {
     }
 
     fn comparison(&mut self) -> Result<Node, Error> {
-        let mut expr = self.term()?;
+        let mut expr = self.range()?;
 
         while matches!(
             self.current()._type, 
             TokenType::Greater |
             TokenType::Less |
             TokenType::GreraterEqual |
-            TokenType::LessEqual
+            TokenType::LessEqual |
+            TokenType::In
         ) {
                 let operator = self.current().clone();
                 self.next();
-                let right = self.term()?;
+                let right = self.range()?;
                 expr = Node::BinaryOperator {
+                    span: expr.span().to(&right.span()),
                     left: Box::new(expr),
                     operator,
                     right: Box::new(right),
@@ -320,6 +648,34 @@ impl <'a>Parser<'a> {
         Ok(expr)
     }
 
+    // `start..end`, `start..=end`, optionally followed by `step n`
+    fn range(&mut self) -> Result<Node, Error> {
+        let expr = self.term()?;
+
+        if !matches!(self.current()._type, TokenType::DotDot | TokenType::DotDotEqual) {
+            return Ok(expr)
+        }
+        let operator = self.current().clone();
+        self.next();
+        let end = self.term()?;
+
+        let mut step = None;
+        if self.current()._type == TokenType::Name("step".to_string()) { // only a keyword here
+            self.next();
+            step = Some(Box::new(self.term()?));
+        }
+
+        Ok(Node::Range {
+            id: self.new_id(),
+            span: self.span_from(expr.span()),
+            start: Box::new(expr),
+            end: Box::new(end),
+            step,
+            inclusive: operator._type == TokenType::DotDotEqual,
+            operator
+        })
+    }
+
     fn term(&mut self) -> Result<Node, Error> {
         let mut expr = self.factor()?;
 
@@ -332,6 +688,7 @@ impl <'a>Parser<'a> {
             self.next();
             let right = self.factor()?;
             expr = Node::BinaryOperator {
+                span: expr.span().to(&right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -354,6 +711,7 @@ impl <'a>Parser<'a> {
             self.next();
             let right = self.unary()?;
             expr = Node::BinaryOperator {
+                span: expr.span().to(&right.span()),
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
@@ -373,7 +731,7 @@ impl <'a>Parser<'a> {
             let operator = self.current().clone();
             self.next();
             let child = self.unary()?;
-            return Ok(Node::UnaryOperator { operator: operator, child: Box::new(child), id: self.new_id() })
+            return Ok(Node::UnaryOperator { span: operator.span().to(&child.span()), operator, child: Box::new(child), id: self.new_id() })
         } else {
             return Ok(self.call()?)
         }
@@ -382,55 +740,204 @@ impl <'a>Parser<'a> {
     fn call(&mut self) -> Result<Node, Error> {
         let mut expr = self.primary()?;
 
-        while self.current()._type == TokenType::ParOpen {
-            self.eat(&TokenType::ParOpen, "ajdsflkajdslj")?;
-    
-            if self.current()._type == TokenType::ParClose {
-                self.eat(&TokenType::ParClose, "closing parenthesis immediately after opening")?;
-                expr = Node::FnCall { id: self.new_id(), name: Box::new(expr), args: vec![] };
+        loop {
+            if self.current()._type == TokenType::ParOpen {
+                expr = self.finish_call(expr)?;
+            } else if self.current()._type == TokenType::SquareOpen {
+                expr = self.index(expr)?;
+            } else if self.current()._type == TokenType::Dot {
+                self.next();
+                let name = self.current().clone();
+                self.eat(&TokenType::Name("".to_string()), "Expected a property name after '.'.")?;
+                expr = Node::Get { id: self.new_id(), span: expr.span().to(&name.span()), object: Box::new(expr), name };
             } else {
-                let mut args = vec![self.get_expression()?];
+                break
+            }
+        }
 
-                while self.current()._type == TokenType::Comma {
-                    self.eat(&TokenType::Comma, "comma")?;
-                    args.push(self.get_expression()?);
+        Ok(expr)
+    }
+
+    // positional arguments come first, then keyword arguments (`f(1, b: 2)`)
+    fn finish_call(&mut self, expr: Node) -> Result<Node, Error> {
+        let paren = self.current().clone();
+        self.eat(&TokenType::ParOpen, "Expected an open parenthesis to call.")?;
+
+        let mut args = Vec::<Node>::new();
+        let mut kwargs = Vec::<(Token, Node)>::new();
+        while self.current()._type != TokenType::ParClose {
+            let peek = self.peek();
+            if matches!(self.current()._type, TokenType::Name(_)) && peek != None && peek.unwrap()._type == TokenType::Colon {
+                let name = self.current().clone();
+                self.next();
+                self.next();
+                if kwargs.iter().any(|(k, _)| k.value == name.value) {
+                    return Err(Error::Syntax(Diagnostic::at("E0104", format!("Keyword argument '{}' given more than once.", name.value), &name)))
                 }
+                kwargs.push((name, self.get_expression()?));
+            } else if !kwargs.is_empty() {
+                return Err(Error::Syntax(Diagnostic::at("E0104", "Positional arguments cannot follow keyword arguments.".to_string(), self.current())))
+            } else {
+                args.push(self.get_expression()?);
+            }
 
-                self.eat(&TokenType::ParClose, "Expected closing parenthesis after argument list.")?;
-                expr = Node::FnCall { id: self.new_id(), name: Box::new(expr), args };
+            if self.current()._type != TokenType::Comma {
+                break
             }
+            self.eat(&TokenType::Comma, "comma")?;
         }
 
-        Ok(expr)
+        self.eat(&TokenType::ParClose, "Expected closing parenthesis after argument list.")?;
+        Ok(Node::FnCall { id: self.new_id(), span: self.span_from(expr.span()), name: Box::new(expr), args, kwargs, paren })
+    }
+
+    // `object[index]` or `object[start:end]`
+    fn index(&mut self, object: Node) -> Result<Node, Error> {
+        let bracket = self.current().clone();
+        self.eat(&TokenType::SquareOpen, "Expected '[' to index.")?;
+
+        let start = if self.current()._type == TokenType::Colon {
+            None
+        } else {
+            Some(Box::new(self.get_expression()?))
+        };
+
+        if self.current()._type != TokenType::Colon {
+            self.eat(&TokenType::SquareClose, "Expected ']' after index.")?;
+            return Ok(Node::Index { id: self.new_id(), span: self.span_from(object.span()), object: Box::new(object), index: start.unwrap(), bracket })
+        }
+
+        self.eat(&TokenType::Colon, "")?;
+        let end = if self.current()._type == TokenType::SquareClose {
+            None
+        } else {
+            Some(Box::new(self.get_expression()?))
+        };
+        self.eat(&TokenType::SquareClose, "Expected ']' after slice.")?;
+        Ok(Node::Slice { id: self.new_id(), span: self.span_from(object.span()), object: Box::new(object), start, end, bracket })
+    }
+
+    // `{` only starts a map in expression position; at the start of a statement it's a block.
+    fn map(&mut self, id: usize) -> Result<Node, Error> {
+        let brace = self.current().clone();
+        self.eat(&TokenType::BrackOpen, "Expected '{' to start a map.")?;
+        let mut entries = Vec::<(Node, Node)>::new();
+
+        while self.current()._type != TokenType::BrackClose {
+            let key = self.get_expression()?;
+            self.eat(&TokenType::Colon, "Expected ':' after map key.")?;
+            let value = self.get_expression()?;
+            entries.push((key, value));
+
+            if self.current()._type != TokenType::Comma {
+                break
+            }
+            self.eat(&TokenType::Comma, "")?;
+        }
+
+        if self.current()._type != TokenType::BrackClose {
+            return Err(Error::Syntax(Diagnostic::at("E0105", "Expected '}' to close map.".to_string(), self.current())))
+        }
+        Ok(Node::Map { id, span: brace.span().to(&self.current().span()), entries, brace })
+    }
+
+    fn list(&mut self, id: usize) -> Result<Node, Error> {
+        let start = self.current().span();
+        self.eat(&TokenType::SquareOpen, "Expected '[' to start a list.")?;
+        let mut items = Vec::<Node>::new();
+
+        while self.current()._type != TokenType::SquareClose {
+            items.push(self.get_expression()?);
+            if self.current()._type != TokenType::Comma {
+                break
+            }
+            self.eat(&TokenType::Comma, "")?; // a trailing comma is fine
+        }
+
+        if self.current()._type != TokenType::SquareClose {
+            return Err(Error::Syntax(Diagnostic::at("E0105", "Expected ']' to close list.".to_string(), self.current())))
+        }
+        Ok(Node::List { id, span: start.to(&self.current().span()), items })
     }
 
     fn primary(&mut self) -> Result<Node, Error> {
+        if matches!(self.current()._type, TokenType::FuncDeclare | TokenType::Pipe) {
+            return self.lambda()
+        }
+
         let id = self.new_id();
+        let span = self.current().span();
         let expr = match &self.current()._type {
             TokenType::Number(value) => Node::Literal {
                 value: Literal::Number(*value),
-                id
+                id,
+                span
             },
             TokenType::Bool(value) => Node::Literal {
                 value: Literal::Bool(*value),
-                id
+                id,
+                span
             },
             TokenType::String(value) => Node::Literal {
                 id,
+                span,
                 value: Literal::String(value.to_string())
             },
             TokenType::ParOpen => {
                 self.next();
                 let node = self.get_expression()?;
-                // self.eat(&Token::ParClose, "Expected clsoing parenthesis to expression.");
-                node
+                self.eat(&TokenType::ParClose, "Expected closing parenthesis to expression.")?;
+                return Ok(node)
+            },
+            TokenType::SquareOpen => self.list(id)?,
+            TokenType::BrackOpen => self.map(id)?,
+            TokenType::Interpolation(parts) => {
+                let parts = parts.clone();
+                self.interpolation(id, span, &parts)?
             },
-            TokenType::None => Node::Literal {value: Literal::None, id: self.new_id() },
-            TokenType::Name(_) => Node::Variable { id: self.new_id(), name: self.current().clone() },
-            _ => return Err(Error::Syntax(format!("Couldn't identify this token: {:?} [{}:{}]", self.current(), self.current().line, self.current().column)))
+            TokenType::None => Node::Literal { value: Literal::None, id: self.new_id(), span },
+            TokenType::This => Node::This { id, span, keyword: self.current().clone() },
+            TokenType::Super => {
+                let keyword = self.current().clone();
+                self.next();
+                self.eat(&TokenType::Dot, "Expected '.' after 'super'.")?;
+                let method = self.current().clone();
+                if !matches!(method._type, TokenType::Name(_)) {
+                    return Err(Error::Syntax(Diagnostic::at("E0100", "Expected a superclass method name.".to_string(), &method)))
+                }
+                Node::Super { id, span: span.to(&method.span()), keyword, method }
+            },
+            TokenType::Name(_) => Node::Variable { id: self.new_id(), span, name: self.current().clone() },
+            _ => return Err(Error::Syntax(Diagnostic::at("E0100", format!("Expected an expression, found '{}'.", self.current().value), self.current())))
         };
 
         self.next();
         Ok(expr)
     }
-}
\ No newline at end of file
+
+    // the literal pieces get the span of the whole string; the expressions have their own
+    fn interpolation(&mut self, id: usize, span: Span, parts: &Vec<StringPart>) -> Result<Node, Error> {
+        let mut nodes = Vec::<Node>::new();
+        for part in parts {
+            match part {
+                StringPart::Literal(s) => nodes.push(Node::Literal {
+                    id: self.new_id(),
+                    span,
+                    value: Literal::String(s.clone())
+                }),
+                StringPart::Expression(tokens) => {
+                    let mut parser = Parser::with_id(tokens, self.current_id);
+                    nodes.push(parser.get_expression()?);
+                    if parser.current()._type != TokenType::Eof {
+                        let token = parser.current();
+                        return Err(Error::Syntax(Diagnostic::at("E0105", format!("Expected '}}' after expression in string, found {:?}.", token.value), &token)))
+                    }
+                    self.current_id = parser.current_id();
+                    self.errors.append(&mut parser.errors); // from blocks in lambdas inside the braces
+                }
+            }
+        }
+
+        Ok(Node::Interpolation { id, span, parts: nodes })
+    }
+}
@@ -1,15 +1,20 @@
-use crate::{ast::*, token::Token, interpreter::Interpreter, error::Error};
+use crate::{ast::*, token::Token, interpreter::Interpreter, error::Error, diagnostic::Diagnostic};
 use std::collections::HashMap;
 
 pub struct Resolver<'a> {
-    scopes: Vec<HashMap<String, bool>>,
+    scopes: Vec<HashMap<String, (bool, usize)>>, // each name's slot, and whether it's been defined yet
     interpreter: &'a mut Interpreter,
-    is_fn: bool
+    is_fn: bool,
+    is_init: bool,
+    is_class: bool,
+    is_subclass: bool,
+    loops: usize, // how many loops deep we are in the current function
+    tries: usize // how many `try` statements deep, where calls have to finish before leaving them
 }
 
 impl <'a>Resolver<'a> {
     pub fn new(interpreter: &'a mut Interpreter) -> Self {
-        Self { scopes: vec![], interpreter, is_fn: false }
+        Self { scopes: vec![], interpreter, is_fn: false, is_init: false, is_class: false, is_subclass: false, loops: 0, tries: 0 }
     }
 
     fn begin_scope(&mut self) {
@@ -20,37 +25,38 @@ impl <'a>Resolver<'a> {
         self.scopes.pop();
     }
 
-    fn declare(&mut self, name: String) {
-        if !self.scopes.is_empty() {
-            let scope = self.scopes.last_mut().unwrap();
-            scope.insert(name, false);
-        }
+    // locals are numbered in the order they're declared in a scope, which is the order the
+    // interpreter fills that scope's slots in. redeclaring a name reuses its slot.
+    fn bind(&mut self, name: String, is_defined: bool) -> Option<usize> {
+        let scope = self.scopes.last_mut()?;
+        let slot = scope.get(&name).map_or(scope.len(), |(_, slot)| *slot);
+        scope.insert(name, (is_defined, slot));
+        Some(slot)
     }
 
-    fn define(&mut self, name: String) {
-        if !self.scopes.is_empty() {
-            let scope = self.scopes.last_mut().unwrap();
-            scope.insert(name, true);
+    fn declare(&mut self, name: String) -> Option<usize> {
+        self.bind(name, false)
+    }
+
+    fn define(&mut self, name: String) -> Option<usize> {
+        self.bind(name, true)
+    }
+
+    // where the declaration `id` puts its value, which is always the innermost scope
+    fn declared(&mut self, id: &usize, slot: Option<usize>) {
+        if let Some(slot) = slot {
+            self.interpreter.locals.insert(*id, (0, slot));
         }
     }
 
-    fn resolve_local(&mut self, expr: &usize, name: String) {
+    fn resolve_local(&mut self, expr: &usize, name: String) -> bool {
         for (i, scope) in self.scopes.iter().rev().enumerate() {
-            if scope.contains_key(&name) {
-                // println!("{:?}; {}", self.scopes, i);
-                self.interpreter.depths.insert(*expr, i);
-                return
+            if let Some((_, slot)) = scope.get(&name) {
+                self.interpreter.locals.insert(*expr, (i, *slot));
+                return true
             }
         }
-
-        // let mut i = self.scopes.len() - 1;
-        // while i >= 0 {
-        //     if self.scopes[i].contains_key(&name) {
-        //         self.interpreter.depths.insert(*expr, self.scopes.len() - 1 - i);
-        //         return
-        //     }
-        //     i = i - 1;
-        // }
+        false
     }
 
     pub fn resolve_block(&mut self, nodes: &Vec<Node>) -> Result<(), Error> {
@@ -63,28 +69,97 @@ impl <'a>Resolver<'a> {
 
     pub fn resolve(&mut self, expr: &Node) -> Result<(), Error> {
         match expr {
-            Node::Block(vec) => {
-                // println!("\x1b[32mMake (Block (resolver.rs)).\x1b[0m");
-                // println!("Made block for: {:#?}", vec);
+            Node::Block { body: vec, .. } => {
                 self.begin_scope();
                 for node in vec {
                     self.resolve(node)?
                 }
-                // println!("\x1b[31mPurge (Block (resolver.rs)).\x1b[0m");
                 self.end_scope();
                 Ok(())
             },
-            Node::Assign { id, name, value} => Ok(self.assign(id, name, value)?),
+            Node::Assign { id, name, value, .. } => Ok(self.assign(id, name, value)?),
             Node::BinaryOperator {left, right, .. } => Ok(self.binary(left, right)?),
             Node::Logical { left, right, .. } => Ok(self.binary(left, right)?),
             Node::UnaryOperator {child, ..} => Ok(self.unary(child)?),
-            Node::Declare {name, value, .. } => Ok(self.resolve_declare(name, value)?),
+            Node::Declare { id, name, value, .. } => Ok(self.resolve_declare(id, name, value)?),
             Node::If { condition, body, else_block, .. } => Ok(self.if_block(condition, body, else_block)?),
-            Node::While { condition, body, .. } => Ok(self.while_block(condition, body)?),
-            Node::Variable {id, name} => Ok(self.variable(id, name)?),
-            Node::DeclareFn { id, name, args, body } => Ok(self.declare_fn(id, name, args, body)?),
-            Node::Return { value, .. } => self.return_statement(value),
-            Node::FnCall { name, args, .. } => self.call(name, args),
+            Node::While { condition, body, increment, .. } => Ok(self.while_block(condition, body, increment)?),
+            Node::ForIn { variable, iterable, body, .. } => self.for_in(variable, iterable, body),
+            Node::Range { start, end, step, .. } => {
+                self.binary(start, end)?;
+                if let Some(s) = step {
+                    self.resolve(s)?;
+                }
+                Ok(())
+            },
+            Node::Break { keyword, .. } | Node::Continue { keyword, .. } => {
+                if self.loops == 0 {
+                    return Err(Error::Syntax(Diagnostic::at("E0200", format!("Cannot use '{}' outside of a loop.", keyword.value), &keyword)))
+                }
+                Ok(())
+            },
+            Node::Variable { id, name, .. } => Ok(self.variable(id, name)?),
+            Node::DeclareFn { id, name, args, body, .. } => Ok(self.declare_fn(id, name, args, body)?),
+            Node::Lambda { args, body, .. } => self.function(args, body, false),
+            Node::Return { keyword, value, .. } => self.return_statement(keyword, value),
+            Node::Throw { value, .. } => self.resolve(value),
+            Node::Try { body, catch, finally, .. } => {
+                self.tries += 1;
+                self.resolve(body)?;
+                if let Some((name, handler)) = catch {
+                    self.begin_scope();
+                    self.define(name.value.clone());
+                    self.resolve(handler)?;
+                    self.end_scope();
+                }
+                if let Some(f) = finally {
+                    self.resolve(f)?;
+                }
+                self.tries -= 1;
+                Ok(())
+            },
+            Node::FnCall { name, args, kwargs, .. } => {
+                self.call(name, args)?;
+                for (_, value) in kwargs {
+                    self.resolve(value)?;
+                }
+                Ok(())
+            },
+            Node::Interpolation { parts, .. } => self.resolve_block(parts),
+            Node::Class { id, name, superclass, methods, .. } => self.declare_class(id, name, superclass, methods),
+            Node::Get { object, .. } => self.resolve(object),
+            Node::Set { object, value, .. } => {
+                self.resolve(value)?;
+                self.resolve(object)
+            },
+            Node::This { id, keyword, .. } => {
+                if !self.is_class {
+                    return Err(Error::Syntax(Diagnostic::at("E0201", "Cannot use 'this' outside of a class.".to_string(), &keyword)))
+                }
+                self.resolve_local(id, keyword.value.clone());
+                Ok(())
+            },
+            Node::Super { id, keyword, .. } => {
+                if !self.is_subclass {
+                    return Err(Error::Syntax(Diagnostic::at("E0202", "Cannot use 'super' outside of a class with a superclass.".to_string(), &keyword)))
+                }
+                self.resolve_local(id, keyword.value.clone());
+                Ok(())
+            },
+            Node::List { items, .. } => self.resolve_block(items),
+            Node::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve(key)?;
+                    self.resolve(value)?;
+                }
+                Ok(())
+            },
+            Node::Index { object, index, .. } => self.binary(object, index),
+            Node::Slice { object, start, end, .. } => self.slice(object, start, end),
+            Node::SetIndex { object, index, value, .. } => {
+                self.binary(object, index)?;
+                self.resolve(value)
+            },
             _ => Ok(()) // literal
         }
     }
@@ -99,30 +174,91 @@ impl <'a>Resolver<'a> {
         Ok(())
     }
 
-    fn declare_fn(&mut self, id: &usize, name: &Token, args: &Vec<Token>, body: &Box<Node>) -> Result<(), Error> {
-        let was = self.is_fn;
-        self.is_fn = true;
+    fn declare_fn(&mut self, id: &usize, name: &Token, args: &Vec<Param>, body: &Node) -> Result<(), Error> {
         self.declare(name.value.clone());
-        self.define(name.value.clone());
+        let slot = self.define(name.value.clone());
+        self.declared(id, slot);
+        self.function(args, body, false)
+    }
+
+    // defaults are resolved in the parameter scope, so they can refer to earlier parameters
+    fn function(&mut self, args: &Vec<Param>, body: &Node, is_init: bool) -> Result<(), Error> {
+        let (was_fn, was_init, loops, tries) = (self.is_fn, self.is_init, self.loops, self.tries);
+        self.is_fn = true;
+        self.is_init = is_init;
+        self.loops = 0; // a loop around the declaration can't be broken out of from inside the body
+        self.tries = 0;
 
-        // println!("\x1b[32mMake (Function (resolver.rs)).\x1b[0m");
         self.begin_scope();
         for arg in args {
-            self.declare(arg.value.clone());
-            self.define(arg.value.clone());
+            if let Some(default) = &arg.default {
+                self.resolve(default)?;
+            }
+            self.declare(arg.name.value.clone());
+            self.define(arg.name.value.clone());
         }
         self.resolve(body)?;
-        self.resolve_local(id, name.value.clone());
-        // println!("\x1b[31mPurge (Function (resolver.rs)).\x1b[0m");
         self.end_scope();
-        self.is_fn = was;
+
+        self.is_fn = was_fn;
+        self.is_init = was_init;
+        self.loops = loops;
+        self.tries = tries;
+        Ok(())
+    }
+
+    // methods live in a scope of their own that holds `this`, which is what `Function::bind` creates.
+    // subclasses get another scope around that one holding `super`.
+    fn declare_class(&mut self, id: &usize, name: &Token, superclass: &Option<Box<Node>>, methods: &Vec<Node>) -> Result<(), Error> {
+        let (was_class, was_subclass) = (self.is_class, self.is_subclass);
+        self.is_class = true;
+        self.is_subclass = superclass.is_some();
+        self.declare(name.value.clone());
+        let slot = self.define(name.value.clone());
+        self.declared(id, slot);
+
+        if let Some(superclass) = superclass {
+            if let Node::Variable { name: super_name, .. } = &**superclass {
+                if super_name.value == name.value {
+                    return Err(Error::Syntax(Diagnostic::at("E0203", "A class cannot inherit from itself.".to_string(), &super_name)))
+                }
+            }
+            self.resolve(superclass)?;
+            self.begin_scope();
+            self.define("super".to_string());
+        }
+
+        self.begin_scope();
+        self.define("this".to_string());
+        for method in methods {
+            if let Node::DeclareFn { name, args, body, .. } = method {
+                self.function(args, body, name.value == "init")?;
+            }
+        }
+        self.end_scope();
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.is_class = was_class;
+        self.is_subclass = was_subclass;
         Ok(())
     }
 
-    fn return_statement(&mut self, value: &Box<Node>) -> Result<(), Error> {
+    fn return_statement(&mut self, keyword: &Token, value: &Box<Node>) -> Result<(), Error> {
         if !self.is_fn {
-            Err(Error::Syntax(format!("Cannot return outside of a function declaration.")))
+            Err(Error::Syntax(Diagnostic::at("E0204", "Cannot return outside of a function declaration.".to_string(), keyword)))
+        } else if self.is_init && !matches!(**value, Node::Literal { value: Literal::None, .. }) {
+            Err(Error::Syntax(Diagnostic::at("E0205", "Cannot return a value from an initializer.".to_string(), keyword)
+                .note("`init` always gives back the new instance".to_string())))
         } else {
+            // a call that's returned straight away can be made once this function has
+            // returned, unless there's a `catch` or `finally` still waiting on it
+            if let Node::FnCall { id, .. } = &**value {
+                if self.tries == 0 {
+                    self.interpreter.tail_calls.insert(*id);
+                }
+            }
             Ok(self.resolve(value)?)
         }
     }
@@ -133,29 +269,51 @@ impl <'a>Resolver<'a> {
         Ok(())
     }
 
-    fn resolve_declare(&mut self, name: &Token, value: &Box<Node>) -> Result<(), Error> {
-        self.declare(name.value.clone());
+    fn resolve_declare(&mut self, id: &usize, name: &Token, value: &Box<Node>) -> Result<(), Error> {
+        let slot = self.declare(name.value.clone());
         self.resolve(value)?;
         self.define(name.value.clone());
+        self.declared(id, slot);
         Ok(())
     }
 
     fn variable(&mut self, id: &usize, name: &Token) -> Result<(), Error> {
         if !self.scopes.is_empty() {
             let scope = self.scopes.last().unwrap();
-            let is_defined = scope.get(&name.value);
-            if is_defined != None && is_defined.unwrap() == &false {
-                return Err(Error::Syntax(format!("'{:?}' cannot be read in its own declaration. [{}:{}]", name, name.line, name.column)))
+            let is_defined = scope.get(&name.value).map(|(defined, _)| *defined);
+            if is_defined == Some(false) {
+                return Err(Error::Syntax(Diagnostic::at("E0206", format!("'{}' cannot be read in its own declaration.", name.value), &name)))
             }
         }
 
-        self.resolve_local(id, name.value.clone());
+        if !self.resolve_local(id, name.value.clone()) && !self.scopes.is_empty() {
+            // it's global, or nothing. the locals here are worth suggesting if it's nothing
+            let names = self.scopes.iter().flat_map(|scope| scope.keys().cloned()).collect();
+            self.interpreter.in_scope.insert(*id, names);
+        }
         Ok(())
     }
 
-    fn while_block(&mut self, condition: &Box<Node>, body: &Box<Node>) -> Result<(), Error> {
+    fn while_block(&mut self, condition: &Box<Node>, body: &Box<Node>, increment: &Option<Box<Node>>) -> Result<(), Error> {
         self.resolve(condition)?;
+        self.loops += 1;
+        self.resolve(body)?;
+        self.loops -= 1;
+        if let Some(i) = increment {
+            self.resolve(i)?;
+        }
+        Ok(())
+    }
+
+    // the loop variable gets a fresh scope on every iteration, so closures capture each value
+    fn for_in(&mut self, variable: &Token, iterable: &Box<Node>, body: &Box<Node>) -> Result<(), Error> {
+        self.resolve(iterable)?;
+        self.begin_scope();
+        self.define(variable.value.clone());
+        self.loops += 1;
         self.resolve(body)?;
+        self.loops -= 1;
+        self.end_scope();
         Ok(())
     }
 
@@ -174,6 +332,17 @@ impl <'a>Resolver<'a> {
         Ok(())
     }
 
+    fn slice(&mut self, object: &Box<Node>, start: &Option<Box<Node>>, end: &Option<Box<Node>>) -> Result<(), Error> {
+        self.resolve(object)?;
+        if let Some(s) = start {
+            self.resolve(s)?;
+        }
+        if let Some(e) = end {
+            self.resolve(e)?;
+        }
+        Ok(())
+    }
+
     fn unary(&mut self, child: &Box<Node>) -> Result<(), Error> {
         self.resolve(child)?;
         Ok(())
@@ -0,0 +1,56 @@
+use crate::{
+    lexer::Lexer,
+    parser::Parser,
+    resolver::Resolver,
+    optimizer::Optimizer,
+    interpreter::{Interpreter, Object},
+    error::Error
+};
+
+// A running program that successive snippets of source can be fed into.
+// Globals, functions and resolved depths all carry over between calls to `run`.
+pub struct Session {
+    pub interpreter: Interpreter,
+    pub optimize: bool, // whether snippets go through the optimizer before running (off unless asked for)
+    pub interactive: bool, // typed at the prompt, where a last expression doesn't need its `;`
+    current_id: usize
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self { interpreter: Interpreter::new(), optimize: false, interactive: false, current_id: 0 }
+    }
+
+    // runs `source`, returning the value of its last statement if that's a bare expression
+    pub fn run(&mut self, source: &String) -> Result<Object, Error> {
+        let tokens = Lexer::new(source).tokenize()?;
+        let mut parser = Parser::with_id(&tokens, self.current_id);
+        parser.trailing = self.interactive;
+        let ast = parser.parse()?;
+        self.current_id = parser.current_id();
+
+        let ast = if self.optimize {
+            // the program is checked as it was written, so mistakes in code the optimizer
+            // drops are still caught. what's left is resolved again to run it
+            Resolver::new(&mut Interpreter::new()).resolve_block(&ast)?;
+            Optimizer::new().optimize(ast)
+        } else {
+            ast
+        };
+        Resolver::new(&mut self.interpreter).resolve_block(&ast)?;
+        let result = match ast.split_last() {
+            Some((last, rest)) if last.is_expression() => {
+                self.interpreter.run(rest).and_then(|_| self.interpreter.traverse(last))
+            },
+            _ => self.interpreter.run(&ast)
+        };
+        self.interpreter.environments.clear(); // a failed snippet shouldn't leave scopes behind
+        result
+    }
+}
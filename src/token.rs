@@ -2,6 +2,7 @@
 pub enum TokenType {
     Number(f64),
     String(String),
+    Interpolation(Vec<StringPart>),
     Name(String),
     // Operators
     Plus,
@@ -18,6 +19,9 @@ pub enum TokenType {
     NotEqual,
     BrackOpen,
     BrackClose,
+    SquareOpen,
+    SquareClose,
+    Colon,
 
     // Control flow
     If,
@@ -25,21 +29,44 @@ pub enum TokenType {
     Not,
     While,
     For,
+    Break,
+    Continue,
     Or,
     And,
+    In,
+    Try,
+    Catch,
+    Finally,
+    Throw,
 
     // Variables and assignment
     Assign,
     // Increment,
     // Decrement,
     FuncDeclare,
+    Pipe, // around the parameters of `|x| x * 2`
     Declare,
     Return,
+
+    // Classes
+    Class,
+    This,
+    Super,
+    Dot,
+
+    // Ranges
+    DotDot,
+    DotDotEqual,
+    Ellipsis, // before a variadic parameter
+
     Bool(bool),
     Separate,
     Eof,
     Comma,
-    None
+    None,
+
+    // Trivia (skipped by the parser)
+    DocComment(String)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -47,5 +74,40 @@ pub struct Token {
     pub _type: TokenType,
     pub value: String,
     pub line: usize,
+    pub column: usize,
+    pub start: usize, // byte offsets of the token in the source, filled in by the lexer
+    pub end: usize
+}
+
+impl Token {
+    pub fn new(_type: TokenType, value: String, line: usize, column: usize) -> Self {
+        Self { _type, value, line, column, start: 0, end: 0 }
+    }
+
+    pub fn span(&self) -> Span {
+        Span { start: self.start, end: self.end, line: self.line, column: self.column }
+    }
+}
+
+// A stretch of source: the bytes `start..end`, and the line and column `start` is at
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
     pub column: usize
+}
+
+impl Span {
+    // from the start of this span to the end of `other`
+    pub fn to(&self, other: &Span) -> Span {
+        Span { end: self.end.max(other.end), ..*self }
+    }
+}
+
+// A piece of an interpolated string: either literal text or the tokens of an embedded `{expression}`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Literal(String),
+    Expression(Vec<Token>)
 }
\ No newline at end of file
@@ -0,0 +1,674 @@
+use std::{collections::HashMap, rc::Rc, cell::RefCell};
+
+use crate::{
+    lexer::Lexer,
+    parser::Parser,
+    resolver::Resolver,
+    optimizer::Optimizer,
+    compiler::Compiler,
+    chunk::{Op, Proto},
+    interpreter::{Interpreter, Object},
+    function::{Function, Call, arrange, was},
+    class::{Class, Instance},
+    iter::Iter,
+    map::Map,
+    token::Token,
+    error::{Error, ErrorKind, RuntimeError},
+    diagnostic::similar,
+    natives,
+    heap,
+    ops
+};
+
+// Global variables, by slot. The compiler gives every global name a slot the first time
+// it sees it, and the slot stays empty until something is stored there.
+pub struct Globals {
+    values: Vec<Option<Object>>,
+    names: Vec<String>,
+    slots: HashMap<String, u32>
+}
+
+impl Default for Globals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Globals {
+    pub fn new() -> Self {
+        Self { values: vec![], names: vec![], slots: HashMap::new() }
+    }
+
+    pub fn slot(&mut self, name: &str) -> u32 {
+        if let Some(slot) = self.slots.get(name) {
+            return *slot
+        }
+        self.values.push(None);
+        self.names.push(name.to_string());
+        let slot = self.values.len() as u32 - 1;
+        self.slots.insert(name.to_string(), slot);
+        slot
+    }
+
+    pub fn define(&mut self, name: &str, value: Object) {
+        let slot = self.slot(name);
+        self.values[slot as usize] = Some(value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Object> {
+        self.slots.get(name).and_then(|slot| self.values[*slot as usize].as_ref())
+    }
+
+    // the names that are actually bound to something
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.names.iter().zip(&self.values).filter(|(_, v)| v.is_some()).map(|(n, _)| n)
+    }
+}
+
+// A variable a closure has captured. It points into the stack while the variable's
+// function is still running, and holds the value itself once it's returned.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Object)
+}
+
+pub struct Closure {
+    pub proto: Rc<Proto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base: usize, // where the function's slot 0 is on the stack
+    iters: usize, // how many loops were already running when it was called
+    unbound: Vec<u16>, // parameters the caller left to their defaults
+    tail: Option<Box<Tail>> // set once a tail call has put another function in the frame
+}
+
+// what a frame was called as, before tail calls put other functions in it, and where the
+// last of those calls was made
+struct Tail {
+    called: Rc<Proto>,
+    from: Rc<Proto>, // the function that made the last tail call
+    ip: usize // just after it
+}
+
+impl Frame {
+    // traces `e` out of the function the frame last tail called, if it has. gives back the
+    // name of what was called in the first place, for the caller to trace
+    fn unwind_tail(&self, e: &mut RuntimeError) -> &str {
+        let name = &self.closure.proto.name.value;
+        match &self.tail {
+            Some(tail) => {
+                e.unwind(name, tail.from.chunk.token(tail.ip - 1));
+                &tail.called.name.value
+            },
+            None => name
+        }
+    }
+}
+
+// an active `try`, and everything to put back when it catches something
+struct Handler {
+    frames: usize,
+    stack: usize,
+    iters: usize,
+    caught: usize,
+    target: usize
+}
+
+// A stack-based virtual machine that runs what the `Compiler` makes of a program. It gives
+// the same results as the tree-walker, errors and tracebacks included, just faster.
+pub struct Vm {
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+    pub globals: Globals,
+    pub optimize: bool, // whether programs go through the optimizer before they're compiled
+    upvalues: Vec<Rc<RefCell<Upvalue>>>, // the open ones
+    handlers: Vec<Handler>,
+    iters: Vec<Iter>, // the state of each `for ... in` loop that's running
+    caught: Vec<RuntimeError>, // errors a handler has caught, in case `finally` needs to raise them again
+    error_class: Rc<Class>
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let mut natives = HashMap::new();
+        natives::define(&mut natives);
+        let mut globals = Globals::new();
+        for (name, value) in natives {
+            globals.define(&name, value);
+        }
+        let error_class = natives::error_class();
+        globals.define("Error", Object::Class(error_class.clone()));
+
+        Self {
+            stack: vec![],
+            frames: vec![],
+            globals,
+            optimize: false,
+            upvalues: vec![],
+            handlers: vec![],
+            iters: vec![],
+            caught: vec![],
+            error_class
+        }
+    }
+
+    pub fn compile(&mut self, source: &String) -> Result<Rc<Proto>, Error> {
+        let tokens = Lexer::new(source).tokenize()?;
+        let ast = Parser::new(&tokens).parse()?;
+        // the resolver still checks the program; the depths it works out are for the tree-walker
+        Resolver::new(&mut Interpreter::new()).resolve_block(&ast)?;
+        let ast = if self.optimize { Optimizer::new().optimize(ast) } else { ast };
+        Compiler::new(&mut self.globals).compile(&ast)
+    }
+
+    // runs `source`, returning the value of its last statement if that's a bare expression
+    pub fn run(&mut self, source: &String) -> Result<Object, Error> {
+        let script = self.compile(source)?;
+        let closure = Rc::new(Closure { proto: script, upvalues: vec![] });
+        self.stack.push(Object::Function(Function::Compiled { closure: closure.clone(), this: None }));
+        self.frames.push(Frame { closure, ip: 0, base: self.stack.len() - 1, iters: self.iters.len(), unbound: vec![], tail: None });
+        self.execute(self.frames.len())
+    }
+
+    // runs until the frame `depth` deep returns, and gives back what it returned
+    fn execute(&mut self, depth: usize) -> Result<Object, Error> {
+        loop {
+            match self.dispatch(depth) {
+                Err(Error::Runtime(e)) => self.catch(e, depth)?,
+                result => return result
+            }
+        }
+    }
+
+    // hands `e` to the innermost handler no shallower than `depth`, tracing the calls it
+    // unwinds through. without one, everything down to `depth` is abandoned and `e` escapes.
+    fn catch(&mut self, mut e: RuntimeError, depth: usize) -> Result<(), Error> {
+        let handler = match self.handlers.last() {
+            Some(h) if h.frames >= depth => self.handlers.pop(),
+            _ => None
+        };
+
+        // whoever called the frame at `depth` traces it, like `Interpreter::call` does
+        let keep = handler.as_ref().map_or(depth - 1, |h| h.frames);
+        for i in (keep.max(depth)..self.frames.len()).rev() {
+            let caller = &self.frames[i - 1];
+            let name = self.frames[i].unwind_tail(&mut e);
+            e.unwind(name, caller.closure.proto.chunk.token(caller.ip - 1));
+        }
+
+        match handler {
+            Some(h) => {
+                self.frames.truncate(h.frames);
+                self.close_upvalues(h.stack);
+                self.stack.truncate(h.stack);
+                self.iters.truncate(h.iters);
+                self.caught.truncate(h.caught);
+                self.stack.push(ops::error_object(e.clone(), &self.error_class));
+                self.caught.push(e);
+                self.frames.last_mut().unwrap().ip = h.target;
+                Ok(())
+            },
+            None => {
+                let entry = &self.frames[depth - 1];
+                entry.unwind_tail(&mut e);
+                let (base, iters) = (entry.base, entry.iters);
+                self.frames.truncate(depth - 1);
+                self.close_upvalues(base);
+                self.stack.truncate(base);
+                self.iters.truncate(iters);
+                while self.handlers.last().is_some_and(|h| h.frames >= depth) {
+                    self.handlers.pop();
+                }
+                Err(Error::Runtime(e))
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().unwrap()
+    }
+
+    // the token the current instruction is blamed on
+    fn token(&self) -> &Token {
+        let frame = self.frames.last().unwrap();
+        frame.closure.proto.chunk.token(frame.ip - 1)
+    }
+
+    fn unknown(&self, slot: u32) -> Error {
+        let name = &self.globals.names[slot as usize];
+        let mut error = Error::at(ErrorKind::Name, self.token(), format!("Unknown variable '{}'.", name));
+        if let Error::Runtime(e) = &mut error {
+            e.help = similar(name, self.globals.names()).map(|s| format!("did you mean `{}`?", s));
+        }
+        error
+    }
+
+    fn traced(&self, e: Error, name: &str) -> Error {
+        match e {
+            Error::Runtime(mut e) => {
+                e.unwind(name, self.token());
+                Error::Runtime(e)
+            },
+            e => e
+        }
+    }
+
+    // numbers are handled here; anything else goes to `ops::binary` for the full rules
+    fn arithmetic(&mut self, op: fn(f64, f64) -> Object) -> Result<(), Error> {
+        let b = self.pop();
+        let a = self.pop();
+        let value = match (a, b) {
+            (Object::Number(a), Object::Number(b)) => op(a, b),
+            (a, b) => ops::binary(self.token(), a, b)?
+        };
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn capture(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        for upvalue in &self.upvalues {
+            if matches!(*upvalue.borrow(), Upvalue::Open(s) if s == slot) {
+                return upvalue.clone()
+            }
+        }
+        let upvalue = heap::upvalue(Upvalue::Open(slot));
+        self.upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    // moves the captured variables from `from` up off the stack and into their upvalues
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= from => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                },
+                _ => true
+            }
+        });
+    }
+
+    // calls `callee`, which is on the stack at `slot` with its arguments above it. the
+    // last `keywords.len()` of them are keyword arguments. natives run there and then;
+    // compiled functions get a frame, for `dispatch` to carry on into.
+    fn call_function(&mut self, callee: &Function, slot: usize, keywords: Vec<Token>) -> Result<(), Error> {
+        match callee {
+            Function::Compiled { closure, this } => {
+                let proto = &closure.proto;
+                let mut unbound = vec![];
+                let argc = self.stack.len() - slot - 1;
+                if !(keywords.is_empty() && proto.simple && argc == proto.params.len()) {
+                    let kwargs = self.keyword_args(keywords);
+                    let args: Vec<Object> = self.stack.drain(slot + 1..).collect();
+                    for (i, value) in arrange(&proto.name.value, &proto.params, args, kwargs)?.into_iter().enumerate() {
+                        match value {
+                            Some(value) => self.stack.push(value),
+                            None => {
+                                self.stack.push(Object::None);
+                                unbound.push(i as u16 + 1);
+                            }
+                        }
+                    }
+                }
+                if let Some(this) = this {
+                    self.stack[slot] = (**this).clone();
+                }
+                self.frames.push(Frame { closure: closure.clone(), ip: 0, base: slot, iters: self.iters.len(), unbound, tail: None });
+                Ok(())
+            },
+            Function::Native { .. } => {
+                let kwargs = self.keyword_args(keywords);
+                let args: Vec<Object> = self.stack.drain(slot + 1..).collect();
+                let result = callee.native(args, kwargs)?;
+                self.stack.truncate(slot);
+                self.stack.push(result);
+                Ok(())
+            },
+            Function::UserDefined { .. } => {
+                Err(Error::new(ErrorKind::Type, format!("{}() belongs to the tree-walker and can't be called from the vm.", callee.name())))
+            }
+        }
+    }
+
+    // pairs the names of keyword arguments with their values, off the top of the stack
+    fn keyword_args(&mut self, keywords: Vec<Token>) -> Vec<(Token, Object)> {
+        let values = self.stack.split_off(self.stack.len() - keywords.len());
+        keywords.into_iter().zip(values).collect()
+    }
+
+    // `Op::Call`, for whatever's on the stack below the arguments
+    fn call(&mut self, argc: usize, keywords: Vec<Token>) -> Result<(), Error> {
+        let slot = self.stack.len() - argc - keywords.len() - 1;
+        match self.stack[slot].clone() {
+            Object::Function(f) => self.call_function(&f, slot, keywords).map_err(|e| self.traced(e, f.name())),
+            Object::Class(class) => { // calling a class makes an instance and runs its initializer
+                let instance = heap::instance(Instance::new(class.clone()));
+                match class.find_method("init") {
+                    Some(init) => {
+                        let init = init.bind(instance);
+                        self.call_function(&init, slot, keywords).map_err(|e| self.traced(e, init.name()))
+                    },
+                    None if argc + keywords.len() != 0 => {
+                        let given = argc + keywords.len();
+                        Err(Error::at(ErrorKind::Argument, self.token(), format!("{}() takes no arguments, but {} {} given.", class.name.value, given, was(given))))
+                    },
+                    None => {
+                        self.stack[slot] = instance;
+                        Ok(())
+                    }
+                }
+            },
+            callee => Err(Error::at(ErrorKind::Type, self.token(), format!("Can only call functions and classes, not {}.", callee)))
+        }
+    }
+
+    // `Op::TailCall`, which makes the call as usual and then gives the frame it made the place
+    // of the one making it. the call is the last thing that frame does, so nothing is lost,
+    // and recursing through tail calls doesn't pile frames up
+    fn tail_call(&mut self, argc: usize, keywords: Vec<Token>) -> Result<(), Error> {
+        let frames = self.frames.len();
+        self.call(argc, keywords)?;
+        // natives have already given back their value, and initializers have to give back
+        // `this` from where they were called. either way the `Return` after this finishes up
+        if self.frames.len() == frames || self.frames[frames].closure.proto.is_initializer {
+            return Ok(())
+        }
+
+        let caller = self.frames.remove(frames - 1);
+        let tail = match caller.tail {
+            Some(mut tail) => {
+                tail.from = caller.closure.proto.clone();
+                tail.ip = caller.ip;
+                tail
+            },
+            None => {
+                let proto = caller.closure.proto.clone();
+                Box::new(Tail { called: proto.clone(), from: proto, ip: caller.ip })
+            }
+        };
+        self.close_upvalues(caller.base);
+        self.iters.truncate(caller.iters);
+        let frame = self.frames.last_mut().unwrap();
+        self.stack.drain(caller.base..frame.base);
+        frame.base = caller.base;
+        frame.iters = caller.iters;
+        frame.tail = Some(tail);
+        Ok(())
+    }
+
+    fn dispatch(&mut self, depth: usize) -> Result<Object, Error> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let op = frame.closure.proto.chunk.code[frame.ip];
+            frame.ip += 1;
+
+            match op {
+                Op::Constant(i) => {
+                    let value = frame.closure.proto.chunk.constants[i as usize].clone();
+                    self.stack.push(value);
+                },
+                Op::None => self.stack.push(Object::None),
+                Op::True => self.stack.push(Object::Bool(true)),
+                Op::False => self.stack.push(Object::Bool(false)),
+                Op::Pop => { self.stack.pop(); },
+
+                Op::GetLocal(slot) => {
+                    let value = self.stack[frame.base + slot as usize].clone();
+                    self.stack.push(value);
+                },
+                Op::SetLocal(slot) => {
+                    let slot = frame.base + slot as usize;
+                    self.stack[slot] = self.stack.pop().unwrap();
+                },
+                Op::GetUpvalue(i) => {
+                    let value = match &*frame.closure.upvalues[i as usize].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone()
+                    };
+                    self.stack.push(value);
+                },
+                Op::SetUpvalue(i) => {
+                    let value = self.stack.pop().unwrap();
+                    match &mut *frame.closure.upvalues[i as usize].borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value
+                    }
+                },
+                Op::GetGlobal(slot) => match &self.globals.values[slot as usize] {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => return Err(self.unknown(slot))
+                },
+                Op::SetGlobal(slot) | Op::DefineGlobal(slot) => {
+                    self.globals.values[slot as usize] = Some(self.stack.pop().unwrap());
+                },
+                Op::GetProperty => {
+                    let object = self.pop();
+                    let value = ops::get(object, self.token())?;
+                    self.stack.push(value);
+                },
+                Op::SetProperty => {
+                    let value = self.pop();
+                    let object = self.pop();
+                    ops::set(object, self.token(), value)?;
+                },
+                Op::GetSuper => {
+                    let superclass = self.pop();
+                    let this = self.pop();
+                    let method = match superclass {
+                        Object::Class(superclass) => ops::get_super(&superclass, this, self.token())?,
+                        _ => return Err(Error::at(ErrorKind::Name, self.token(), "Cannot use 'super' here.".to_string()))
+                    };
+                    self.stack.push(method);
+                },
+
+                Op::Add => self.arithmetic(|a, b| Object::Number(a + b))?,
+                Op::Subtract => self.arithmetic(|a, b| Object::Number(a - b))?,
+                Op::Multiply => self.arithmetic(|a, b| Object::Number(a * b))?,
+                Op::Divide => self.arithmetic(|a, b| Object::Number(a / b))?,
+                Op::Greater => self.arithmetic(|a, b| Object::Bool(a > b))?,
+                Op::Less => self.arithmetic(|a, b| Object::Bool(a < b))?,
+                Op::GreaterEqual => self.arithmetic(|a, b| Object::Bool(a >= b))?,
+                Op::LessEqual => self.arithmetic(|a, b| Object::Bool(a <= b))?,
+                Op::Equal | Op::NotEqual | Op::In => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let value = ops::binary(self.token(), a, b)?;
+                    self.stack.push(value);
+                },
+                Op::Negate | Op::Not => {
+                    let child = self.pop();
+                    let value = ops::unary(self.token(), child)?;
+                    self.stack.push(value);
+                },
+                Op::And | Op::Or => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let value = ops::logical(self.token(), a, b);
+                    self.stack.push(value);
+                },
+
+                Op::Jump(target) => frame.ip = target as usize,
+                Op::JumpIfFalse(target) => {
+                    if !ops::is_truthy(&self.stack.pop().unwrap()) {
+                        frame.ip = target as usize;
+                    }
+                },
+                Op::JumpIfBound(slot, target) => {
+                    if !frame.unbound.contains(&slot) {
+                        frame.ip = target as usize;
+                    }
+                },
+
+                Op::Call(argc) => self.call(argc as usize, vec![])?,
+                Op::CallKw(argc, i) => {
+                    let keywords = frame.closure.proto.chunk.keywords[i as usize].clone();
+                    self.call(argc as usize, keywords)?;
+                },
+                Op::TailCall(argc) => self.tail_call(argc as usize, vec![])?,
+                Op::TailCallKw(argc, i) => {
+                    let keywords = frame.closure.proto.chunk.keywords[i as usize].clone();
+                    self.tail_call(argc as usize, keywords)?;
+                },
+                Op::Closure(i) => {
+                    let proto = frame.closure.proto.chunk.functions[i as usize].clone();
+                    let (enclosing, base) = (frame.closure.clone(), frame.base);
+                    let upvalues = proto.captures.iter().map(|c| match c.local {
+                        true => self.capture(base + c.index as usize),
+                        false => enclosing.upvalues[c.index as usize].clone()
+                    }).collect();
+                    let closure = heap::closure(Closure { proto, upvalues });
+                    self.stack.push(Object::Function(Function::Compiled { closure, this: None }));
+                },
+                Op::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                },
+                Op::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    let value = if frame.closure.proto.is_initializer { self.stack[frame.base].clone() } else { value };
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    self.iters.truncate(frame.iters);
+                    while self.handlers.last().is_some_and(|h| h.frames > self.frames.len()) {
+                        self.handlers.pop();
+                    }
+                    if self.frames.len() < depth {
+                        return Ok(value)
+                    }
+                    self.stack.push(value);
+                },
+
+                Op::Class(count, inherits) => {
+                    let methods: Vec<Object> = self.stack.drain(self.stack.len() - count as usize..).collect();
+                    let superclass = match self.stack.last() {
+                        Some(Object::Class(superclass)) if inherits => Some(superclass.clone()),
+                        _ => None
+                    };
+                    let mut class = Class { name: self.token().clone(), superclass, methods: HashMap::new() };
+                    for method in methods {
+                        if let Object::Function(method) = method {
+                            class.methods.insert(method.name().to_string(), method);
+                        }
+                    }
+                    self.stack.push(Object::Class(heap::class(class)));
+                },
+                Op::Inherit => {
+                    if !matches!(self.stack.last(), Some(Object::Class(_))) {
+                        return Err(Error::at(ErrorKind::Type, self.token(), format!("Superclass must be a class, not {}.", self.stack.last().unwrap())))
+                    }
+                },
+
+                Op::List(count) => {
+                    let items = self.stack.split_off(self.stack.len() - count as usize);
+                    self.stack.push(heap::list(items));
+                },
+                Op::Map(count) => {
+                    let entries = self.stack.split_off(self.stack.len() - 2 * count as usize);
+                    let mut map = Map::new();
+                    let mut entries = entries.into_iter();
+                    while let (Some(k), Some(v)) = (entries.next(), entries.next()) {
+                        map.insert(ops::key(&k, self.token())?, k, v);
+                    }
+                    self.stack.push(heap::map(map));
+                },
+                Op::Index => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    let value = ops::index(object, index, self.token())?;
+                    self.stack.push(value);
+                },
+                Op::Slice(has_start, has_end) => {
+                    let end = if has_end { Some(self.pop()) } else { None };
+                    let start = if has_start { Some(self.pop()) } else { None };
+                    let object = self.pop();
+                    let value = ops::slice(object, start, end, self.token())?;
+                    self.stack.push(value);
+                },
+                Op::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let object = self.pop();
+                    ops::set_index(object, index, value, self.token())?;
+                },
+                Op::Range(inclusive, has_step) => {
+                    let step = if has_step { self.pop() } else { Object::Number(1.0) };
+                    let end = self.pop();
+                    let start = self.pop();
+                    let range = ops::range(self.token(), start, end, step, inclusive)?;
+                    self.stack.push(range);
+                },
+                Op::Interpolate(count) => {
+                    let parts = self.stack.split_off(self.stack.len() - count as usize);
+                    let string: String = parts.iter().map(|p| p.to_string()).collect();
+                    self.stack.push(Object::String(string.into()));
+                },
+
+                Op::Throw => {
+                    let value = self.pop();
+                    return Err(ops::throw(value, self.token(), &self.error_class))
+                },
+                Op::Try(target) => self.handlers.push(Handler {
+                    frames: self.frames.len(),
+                    stack: self.stack.len(),
+                    iters: self.iters.len(),
+                    caught: self.caught.len(),
+                    target: target as usize
+                }),
+                Op::EndTry => { self.handlers.pop(); },
+                Op::Rethrow => return Err(Error::Runtime(self.caught.pop().unwrap())),
+
+                Op::IterStart => {
+                    let closure = frame.closure.clone();
+                    let token = closure.proto.chunk.token(frame.ip - 1);
+                    let iterable = self.pop();
+                    let iter = Iter::new(self, iterable, token)?;
+                    self.iters.push(iter);
+                },
+                Op::IterNext(exit) => {
+                    let closure = frame.closure.clone();
+                    let token = closure.proto.chunk.token(frame.ip - 1);
+                    let mut iter = self.iters.pop().unwrap();
+                    let next = iter.next(self, token);
+                    self.iters.push(iter);
+                    match next? {
+                        Some(item) => self.stack.push(item),
+                        None => self.frames.last_mut().unwrap().ip = exit as usize
+                    }
+                },
+                Op::IterEnd => { self.iters.pop(); }
+            }
+        }
+    }
+}
+
+impl Call for Vm {
+    fn invoke(&mut self, f: &mut Function, args: Vec<Object>) -> Result<Object, Error> {
+        let slot = self.stack.len();
+        self.stack.push(Object::Function(f.clone()));
+        self.stack.extend(args);
+        let frames = self.frames.len();
+        if let Err(e) = self.call_function(f, slot, vec![]) {
+            self.stack.truncate(slot);
+            return Err(e)
+        }
+        if self.frames.len() > frames {
+            self.execute(self.frames.len())
+        } else {
+            Ok(self.pop())
+        }
+    }
+}
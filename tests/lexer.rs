@@ -0,0 +1,103 @@
+use neptune::{lexer::Lexer, token::{TokenType, StringPart}, error::Error};
+
+fn types(source: &str) -> Vec<TokenType> {
+    match Lexer::new(&source.to_string()).tokenize() {
+        Ok(tokens) => tokens.into_iter().map(|t| t._type).collect(),
+        Err(_) => panic!("{:?} didn't lex", source)
+    }
+}
+
+// the code, line and column of the syntax error `source` fails with
+fn error(source: &str) -> (&'static str, usize, usize) {
+    match Lexer::new(&source.to_string()).tokenize() {
+        Err(Error::Syntax(d)) => (d.code, d.line, d.column),
+        Err(_) => panic!("{:?} failed with something other than a syntax error", source),
+        Ok(_) => panic!("{:?} lexed", source)
+    }
+}
+
+#[test]
+fn comments_are_skipped() {
+    let expected = vec![TokenType::Number(1.0), TokenType::Separate, TokenType::Eof];
+    assert_eq!(types("1; // trailing"), expected);
+    assert_eq!(types("/* before */ 1;"), expected);
+    assert_eq!(types("/* outer /* inner */ still outer */ 1;"), expected);
+    assert_eq!(types("/*\nspans\nlines\n*/\n1;"), expected);
+    assert_eq!(types("//// not a doc comment\n1;"), expected);
+}
+
+#[test]
+fn doc_comments_are_kept() {
+    assert_eq!(types("///  what it does \nfn f() {}")[0], TokenType::DocComment("what it does".to_string()));
+}
+
+#[test]
+fn unterminated_block_comment() {
+    assert_eq!(error("1; /* /* */"), ("E0002", 1, 4));
+}
+
+#[test]
+fn escapes() {
+    assert_eq!(types(r#""\t\n\r\0\\\"""#)[0], TokenType::String("\t\n\r\0\\\"".to_string()));
+    assert_eq!(types(r#""\u{e9}\u{1F600}""#)[0], TokenType::String("é😀".to_string()));
+    assert_eq!(types(r#"r"\n\q""#)[0], TokenType::String("\\n\\q".to_string()));
+    assert_eq!(types("\"\"\"\nlines\n  kept\"\"\"")[0], TokenType::String("lines\n  kept".to_string()));
+}
+
+#[test]
+fn escape_errors_point_at_the_backslash() {
+    assert_eq!(error(r#"let s = "ok \q";"#), ("E0003", 1, 13));
+    assert_eq!(error("let s =\n  \"\\u00e9\";"), ("E0003", 2, 4));
+    assert_eq!(error(r#""\u{110000}""#), ("E0003", 1, 2));
+    assert_eq!(error(r#""\u{e9""#), ("E0003", 1, 2));
+}
+
+#[test]
+fn unterminated_string() {
+    assert_eq!(error("print(\"open);"), ("E0002", 1, 7));
+    assert_eq!(error("\"ends in a backslash\\"), ("E0002", 1, 1));
+}
+
+#[test]
+fn interpolation() {
+    let parts = match &types(r#""a {x + 1} b {"c"}""#)[0] {
+        TokenType::Interpolation(parts) => parts.clone(),
+        other => panic!("expected an interpolation, found {:?}", other)
+    };
+    assert_eq!(parts.len(), 4);
+    assert_eq!(parts[0], StringPart::Literal("a ".to_string()));
+    match &parts[1] {
+        StringPart::Expression(tokens) => {
+            let inner: Vec<_> = tokens.iter().map(|t| (t._type.clone(), t.line, t.column)).collect();
+            // the embedded tokens keep their real position in the file
+            assert_eq!(inner[0], (TokenType::Name("x".to_string()), 1, 5));
+            assert_eq!(inner[2], (TokenType::Number(1.0), 1, 9));
+        },
+        other => panic!("expected an expression, found {:?}", other)
+    }
+    assert_eq!(parts[2], StringPart::Literal(" b ".to_string()));
+    assert_eq!(types(r#""\{not\}""#)[0], TokenType::String("{not}".to_string()));
+    assert_eq!(error(r#"print("{}");"#), ("E0005", 1, 9));
+}
+
+#[test]
+fn completeness() {
+    let complete = |source: &str| Lexer::is_complete(&source.to_string());
+    assert!(complete("print(1);"));
+    assert!(!complete("fn f() {"));
+    assert!(!complete("print((1"));
+    assert!(complete("print((1));"));
+    assert!(complete("// { in a comment"));
+    // a stray closer is an error to report, not a reason to keep reading
+    assert!(complete("}"));
+}
+
+#[test]
+fn tokens_know_their_bytes() {
+    let source = "let é = \"hi\";".to_string();
+    let tokens = Lexer::new(&source).tokenize().ok().unwrap();
+    let text: Vec<_> = tokens.iter().map(|t| &source[t.start..t.end]).collect();
+    assert_eq!(text, vec!["let", "é", "=", "\"hi\"", ";", ""]);
+    // spans count bytes, columns count characters
+    assert_eq!((tokens[3].line, tokens[3].column), (1, 9));
+}
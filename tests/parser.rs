@@ -0,0 +1,90 @@
+use neptune::{lexer::Lexer, parser::Parser, ast::Node, diagnostic::Diagnostic, error::Error};
+
+fn parse(source: &str) -> (Vec<Node>, Vec<Diagnostic>) {
+    let tokens = match Lexer::new(&source.to_string()).tokenize() {
+        Ok(tokens) => tokens,
+        Err(_) => panic!("{:?} didn't lex", source)
+    };
+    Parser::new(&tokens).parse_recovering()
+}
+
+fn declared(node: &Node) -> Option<&str> {
+    match node {
+        Node::Declare { name, .. } => Some(&name.value),
+        _ => None
+    }
+}
+
+#[test]
+fn every_error_is_reported() {
+    let (_, errors) = parse("let a = ;\nlet b = 2\nprint(b);\nfn f( { return 1; }\nif (a = 1) {}");
+    let found: Vec<_> = errors.iter().map(|d| (d.code, d.line, d.column)).collect();
+    assert_eq!(found, vec![("E0100", 1, 9), ("E0101", 2, 10), ("E0100", 4, 7), ("E0100", 5, 7)]);
+    assert_eq!(errors[1].help.as_deref(), Some("add a `;` here"));
+    assert_eq!(errors[3].help.as_deref(), Some("did you mean `==`?"));
+}
+
+#[test]
+fn error_columns_count_characters() {
+    let (_, errors) = parse("let s = \"é\" let t = 1;");
+    assert_eq!((errors[0].code, errors[0].line, errors[0].column), ("E0101", 1, 12));
+}
+
+#[test]
+fn a_group_needs_its_closing_parenthesis() {
+    let (nodes, errors) = parse("let a = (1 + 2;\nlet b = (1 2);\nlet c = (3);");
+    let found: Vec<_> = errors.iter().map(|d| (d.code, d.line, d.column)).collect();
+    assert_eq!(found, vec![("E0100", 1, 15), ("E0100", 2, 12)]);
+    assert_eq!(nodes.last().and_then(declared), Some("c"));
+}
+
+#[test]
+fn statements_around_errors_are_kept() {
+    let (nodes, errors) = parse("let a = 1;\nlet b = ;\nlet c = 3;\nlet d = (;\nlet e = 5;");
+    assert_eq!(errors.len(), 2);
+    let names: Vec<_> = nodes.iter().filter_map(declared).collect();
+    assert_eq!(names, vec!["a", "c", "e"]);
+}
+
+#[test]
+fn recovery_skips_a_whole_broken_block() {
+    let (nodes, errors) = parse("fn f() { let x = ; return x; }\nlet after = 1;");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(nodes.last().and_then(declared), Some("after"));
+}
+
+#[test]
+fn parse_fails_with_every_error() {
+    let tokens = Lexer::new(&"let a = ;\nlet b = ;".to_string()).tokenize().ok().unwrap();
+    match Parser::new(&tokens).parse() {
+        Err(Error::Parse(errors)) => assert_eq!(errors.len(), 2),
+        _ => panic!("expected both errors")
+    }
+
+    let tokens = Lexer::new(&"let a = ;".to_string()).tokenize().ok().unwrap();
+    assert!(matches!(Parser::new(&tokens).parse(), Err(Error::Syntax(d)) if d.line == 1));
+}
+
+#[test]
+fn a_clean_file_has_no_errors() {
+    let (nodes, errors) = parse("let a = 1;\nfn f(x) { return x; }\nprint(f(a));");
+    assert!(errors.is_empty());
+    assert_eq!(nodes.len(), 3);
+}
+
+#[test]
+fn nodes_cover_their_source() {
+    let source = "let a = [1, 2];\n{ print(a[0] + f(1, 2)); }";
+    let (nodes, errors) = parse(source);
+    assert!(errors.is_empty());
+    let text: Vec<_> = nodes.iter().map(|n| &source[n.span().start..n.span().end]).collect();
+    assert_eq!(text, vec!["let a = [1, 2];", "{ print(a[0] + f(1, 2)); }"]);
+
+    let call = match &nodes[1] {
+        Node::Block { body, .. } => &body[0],
+        other => panic!("expected a block, found {}", other)
+    };
+    let span = call.span();
+    assert_eq!(&source[span.start..span.end], "print(a[0] + f(1, 2))");
+    assert_eq!((span.line, span.column), (2, 3));
+}
@@ -0,0 +1,156 @@
+use std::{fs, io::Write, path::Path, process::{Command, Stdio}};
+use rustyline::{Context, completion::Completer, highlight::Highlighter, history::History};
+use neptune::helper::ReplHelper;
+
+const RESET: &str = "\x1b[0m";
+
+// feeds `input` to the prompt line by line and gives back everything it printed after the banner
+fn prompt(input: &str) -> Vec<String> {
+    prompt_in(Path::new(env!("CARGO_TARGET_TMPDIR")), input)
+}
+
+fn prompt_in(dir: &Path, input: &str) -> Vec<String> {
+    // the prompt keeps its history file in the working directory
+    let mut child = Command::new(env!("CARGO_BIN_EXE_neptune"))
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("couldn't run neptune");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8_lossy(&output.stdout).lines().skip(3).map(|line| line.to_string()).collect()
+}
+
+#[test]
+fn lines_share_one_session() {
+    let output = prompt("let x = 1;\nfn f() { return x + 1; }\nx = 5;\nprint(f());\n");
+    assert_eq!(output[0], "6");
+}
+
+#[test]
+fn errors_dont_end_the_session() {
+    let output = prompt("let x = 2;\nprint(y);\nprint(x);\n");
+    assert!(output[0].contains("Unknown variable 'y'"), "{:?}", output);
+    assert!(output.iter().any(|line| line.contains("did you mean `x`?")), "{:?}", output);
+    assert_eq!(output.last().unwrap(), "2");
+}
+
+#[test]
+fn history_is_kept_between_sessions() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("history");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    prompt_in(&dir, "let a = 1;\n");
+    prompt_in(&dir, "let b = 2;\n");
+    let history = fs::read_to_string(dir.join("npt-history.txt")).unwrap();
+    assert!(history.contains("let a = 1;") && history.contains("let b = 2;"), "{:?}", history);
+}
+
+#[test]
+fn expressions_are_echoed() {
+    let output = prompt("1 + 2;\nlet x = 4;\nx * 2\nnone\nprint(x)\n");
+    assert_eq!(output, vec!["3", "8", "4"]);
+}
+
+#[test]
+fn input_continues_until_brackets_close() {
+    let output = prompt("fn f(a) {\n  return a * 10;\n}\nprint(f(\n  4\n))\n");
+    assert_eq!(output, vec!["40"]);
+}
+
+#[test]
+fn commands() {
+    let output = prompt(":help\n");
+    assert!(output[0].starts_with("Commands:"), "{:?}", output);
+
+    let output = prompt("let x = 1;\n:reset\nx\n:unknown\n");
+    assert_eq!(output[0], "Session reset.");
+    assert!(output[1].contains("Unknown variable 'x'"), "{:?}", output);
+    assert!(output.last().unwrap().contains("Unknown command ':unknown'"), "{:?}", output);
+
+    let output = prompt(":tokens 1;\n");
+    assert_eq!(output, vec!["[1:1] Number(1.0)", "[1:2] Separate", "[1:3] Eof"]);
+}
+
+#[test]
+fn optimizer_changes_are_listed() {
+    let output = prompt(":opt print(1 + 2 * 3);\n:opt let x = 1;\n");
+    assert_eq!(output, vec!["[1:7] `1 + 2 * 3` folded into 7", "Nothing to optimize."]);
+}
+
+fn complete(helper: &ReplHelper, line: &str) -> (usize, Vec<String>) {
+    let history = History::new();
+    helper.complete(line, line.len(), &Context::new(&history)).unwrap()
+}
+
+#[test]
+fn highlighting_keeps_the_text() {
+    let helper = ReplHelper::new();
+    let line = "let s = \"héé\"; print(s + 1)";
+    let highlighted = helper.highlight(line, 0);
+    let stripped = highlighted.split('\x1b')
+        .enumerate()
+        .map(|(i, part)| if i == 0 { part } else { &part[part.find('m').unwrap() + 1..] })
+        .collect::<String>();
+    assert_eq!(stripped, line);
+}
+
+#[test]
+fn tokens_are_coloured() {
+    let helper = ReplHelper::new();
+    let highlighted = helper.highlight("let x = 1 + \"a\";", 100);
+    assert!(highlighted.contains(&format!("\x1b[35mlet{}", RESET)));
+    assert!(highlighted.contains(&format!("\x1b[33m1{}", RESET)));
+    assert!(highlighted.contains(&format!("\x1b[36m+{}", RESET)));
+    assert!(highlighted.contains(&format!("\x1b[32m\"a\"{}", RESET)));
+    assert!(highlighted.contains(" x "));
+}
+
+#[test]
+fn commands_and_unfinished_input_are_left_alone() {
+    let helper = ReplHelper::new();
+    assert_eq!(helper.highlight(":help let", 0), ":help let");
+    assert_eq!(helper.highlight("let s = \"open", 0), "let s = \"open");
+}
+
+#[test]
+fn brackets_match() {
+    let helper = ReplHelper::new();
+    let bracket = |c: &str| format!("\x1b[1;34m{}{}", c, RESET);
+    // on the opening bracket, and just after the closing one
+    for pos in [1, 11] {
+        let highlighted = helper.highlight("f((1), { })", pos);
+        assert!(highlighted.contains(&bracket("(")), "{:?}", highlighted);
+        assert!(highlighted.contains(&bracket(")")), "{:?}", highlighted);
+        assert!(!highlighted.contains(&bracket("{")), "{:?}", highlighted);
+    }
+    let highlighted = helper.highlight("f((1), { })", 7);
+    assert!(highlighted.contains(&bracket("{")) && highlighted.contains(&bracket("}")));
+    assert!(!highlighted.contains(&bracket("(")));
+}
+
+#[test]
+fn completes_keywords_and_names() {
+    let mut helper = ReplHelper::new();
+    assert_eq!(complete(&helper, "let x = tr"), (8, vec!["true".to_string(), "try".to_string()]));
+    assert_eq!(complete(&helper, "print(counter"), (6, vec![]));
+
+    helper.names = vec!["counter".to_string(), "count".to_string(), "total".to_string()];
+    assert_eq!(complete(&helper, "print(coun"), (6, vec!["count".to_string(), "counter".to_string()]));
+    assert_eq!(complete(&helper, "é + tot"), (5, vec!["total".to_string()]));
+}
+
+#[test]
+fn nothing_to_complete() {
+    let helper = ReplHelper::new();
+    assert_eq!(complete(&helper, "print("), (6, vec![]));
+    assert_eq!(complete(&helper, ""), (0, vec![]));
+}
+
+#[test]
+fn doc_comments_are_dimmed() {
+    let helper = ReplHelper::new();
+    let highlighted = helper.highlight("/// docs\nfn f() {} // plain", 100);
+    assert!(highlighted.starts_with(&format!("\x1b[90m/// docs{}", RESET)), "{:?}", highlighted);
+}
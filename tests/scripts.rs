@@ -0,0 +1,77 @@
+use std::{env, fs, path::{Path, PathBuf}, process::{Command, Output}};
+
+// Every script in tests/scripts is run by the tree-walking interpreter, by the vm, and by the
+// tree-walker with the optimizer turned on, and whatever one prints (errors included) the
+// others have to print too. What the interpreter prints (stdout, then stderr) is also checked
+// against the script's .out file; run with NEPTUNE_BLESS=1 to write those from the current
+// output instead.
+
+fn run(script: &Path, flags: &[&str]) -> Output {
+    // scripts are run from the crate root by relative path, so the paths in diagnostics don't
+    // depend on where the repo is checked out
+    Command::new(env!("CARGO_BIN_EXE_neptune"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(flags)
+        .arg(script)
+        .output()
+        .expect("couldn't run neptune")
+}
+
+fn scripts() -> Vec<PathBuf> {
+    let dir = Path::new("tests").join("scripts");
+    let mut scripts: Vec<_> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join(&dir)).unwrap()
+        .map(|entry| dir.join(entry.unwrap().file_name()))
+        .filter(|path| path.extension().is_some_and(|e| e == "nt"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+    scripts
+}
+
+// runs every script both ways, listing the ones where the output differs
+fn compare(name: &str, flags: &[&str]) {
+    let mut failures = vec![];
+    for script in scripts() {
+        let (walked, other) = (run(&script, &[]), run(&script, flags));
+        if walked.stdout != other.stdout || walked.stderr != other.stderr {
+            failures.push(format!(
+                "{}\n--- interpreter ---\n{}{}\n--- {} ---\n{}{}",
+                script.display(),
+                String::from_utf8_lossy(&walked.stdout), String::from_utf8_lossy(&walked.stderr),
+                name,
+                String::from_utf8_lossy(&other.stdout), String::from_utf8_lossy(&other.stderr)
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "the {} and the interpreter disagree on:\n\n{}", name, failures.join("\n\n"));
+}
+
+#[test]
+fn vm_matches_interpreter() {
+    compare("vm", &["--vm"]);
+}
+
+#[test]
+fn optimizer_changes_nothing() {
+    compare("optimized interpreter", &["--opt"]);
+}
+
+#[test]
+fn scripts_print_what_they_should() {
+    let blessing = env::var_os("NEPTUNE_BLESS").is_some();
+    let mut failures = vec![];
+    for script in scripts() {
+        let output = run(&script, &[]);
+        let actual = String::from_utf8_lossy(&[output.stdout, output.stderr].concat()).into_owned();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(script.with_extension("out"));
+        if blessing {
+            fs::write(&path, &actual).unwrap();
+            continue
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_default();
+        if actual != expected {
+            failures.push(format!("{}\n--- expected ---\n{}\n--- actual ---\n{}", path.display(), expected, actual));
+        }
+    }
+    assert!(failures.is_empty(), "unexpected output (NEPTUNE_BLESS=1 updates it) from:\n\n{}", failures.join("\n\n"));
+}